@@ -1,6 +1,170 @@
+use std::env;
+use std::path::{Path, PathBuf};
+
 fn main() {
-    // Replace this path with the actual directory where libraw.lib is located.
-    println!("cargo:rustc-link-search=native=C:\\Users\\hanba\\LibRaw-0.21.3\\lib");
-    // Instruct the linker to link against libraw (the name should match the library name, e.g. "libraw" if the file is libraw.lib).
-    println!("cargo:rustc-link-lib=dylib=libraw");
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=src/libraw_shim.c");
+    for var in ["LIBRAW_DIR", "LIBRAW_LIB_DIR", "LIBRAW_INCLUDE_DIR", "LIBRAW_SRC_DIR"] {
+        println!("cargo:rerun-if-env-changed={}", var);
+    }
+
+    // Pure-Rust builds need neither LibRaw nor the shim.
+    if env::var_os("CARGO_FEATURE_LIBRAW").is_none() {
+        return;
+    }
+    let vendored = env::var_os("CARGO_FEATURE_VENDORED").is_some();
+    let dynamic = env::var_os("CARGO_FEATURE_DYNAMIC").is_some();
+    if vendored && dynamic {
+        panic!("the `vendored` and `dynamic` features are mutually exclusive");
+    }
+    let include_paths = if vendored {
+        build_vendored()
+    } else {
+        // With `dynamic`, LibRaw is loaded at runtime; only its headers are needed for the shim.
+        find_libraw(!dynamic)
+    };
+
+    // The libraw_data_t accessors are compiled against the same LibRaw headers we link with.
+    let mut shim = cc::Build::new();
+    shim.file("src/libraw_shim.c");
+    for path in &include_paths {
+        shim.include(path);
+    }
+    shim.compile("libraw_shim");
+}
+
+/// Compiles the LibRaw sources into a static library linked into the binary
+/// and returns the include path of their headers.
+/// The sources are read from LIBRAW_SRC_DIR, or vendor/LibRaw by default.
+fn build_vendored() -> Vec<PathBuf> {
+    let src_dir = env::var_os("LIBRAW_SRC_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap()).join("vendor").join("LibRaw"));
+    if !src_dir.join("libraw").join("libraw.h").is_file() {
+        panic!(
+            "\n\nThe `vendored` feature needs the LibRaw sources, but {} does not contain libraw/libraw.h.\n\
+             Fetch them with\n  git clone --branch 0.21.3 https://github.com/LibRaw/LibRaw.git vendor/LibRaw\n\
+             or set LIBRAW_SRC_DIR to an unpacked LibRaw source release.\n",
+            src_dir.display()
+        );
+    }
+    println!("cargo:rerun-if-changed={}", src_dir.display());
+
+    let sources = library_sources(&src_dir);
+
+    let mut build = cc::Build::new();
+    build
+        .cpp(true)
+        .files(&sources)
+        .include(&src_dir)
+        // Static library: no __declspec(dllimport) on Windows.
+        .define("LIBRAW_NODLL", None)
+        .warnings(false);
+    if env::var("CARGO_CFG_TARGET_ENV").as_deref() == Ok("msvc") {
+        build.define("_CRT_SECURE_NO_WARNINGS", None).flag("/EHsc");
+    }
+    // cc emits the static link flags for libraw and the C++ runtime.
+    build.compile("raw");
+    vec![src_dir]
+}
+
+/// The translation units of the LibRaw library, as listed by `lib_libraw_la_SOURCES` in
+/// LibRaw's Makefile.am. The tree also holds samples and alternative implementations
+/// (e.g. the RawSpeed and DNG SDK glue) that must not be compiled in.
+fn library_sources(src_dir: &Path) -> Vec<PathBuf> {
+    // Older releases build a plain static library instead of a libtool one.
+    const SOURCE_VARIABLES: [&str; 2] = ["lib_libraw_la_SOURCES", "lib_libraw_a_SOURCES"];
+    let makefile = src_dir.join("Makefile.am");
+    let text = std::fs::read_to_string(&makefile)
+        .unwrap_or_else(|e| panic!("cannot read {}: {}", makefile.display(), e));
+    // Join continuation lines so the variable is on one line.
+    let text = text.replace("\\\r\n", " ").replace("\\\n", " ");
+    let line = text
+        .lines()
+        .find(|line| line.split('=').next().is_some_and(|name| SOURCE_VARIABLES.contains(&name.trim())))
+        .unwrap_or_else(|| panic!("{} does not define lib_libraw_la_SOURCES", makefile.display()));
+    let sources: Vec<PathBuf> = line
+        .split_once('=')
+        .unwrap()
+        .1
+        .split_whitespace()
+        .filter(|file| file.ends_with(".cpp"))
+        .map(|file| src_dir.join(file))
+        .collect();
+    if let Some(missing) = sources.iter().find(|file| !file.is_file()) {
+        panic!("{} lists {}, which does not exist", makefile.display(), missing.display());
+    }
+    sources
+}
+
+/// Returns the include paths of the LibRaw headers and, if `link` is set, emits the link flags.
+/// An explicit LIBRAW_DIR / LIBRAW_LIB_DIR wins over pkg-config.
+fn find_libraw(link: bool) -> Vec<PathBuf> {
+    if let Some(include_paths) = from_env(link) {
+        return include_paths;
+    }
+    // libraw_r is the thread-safe build; prefer it since decoding runs off the UI thread.
+    let mut errors = Vec::new();
+    for name in ["libraw_r", "libraw"] {
+        match pkg_config::Config::new().atleast_version("0.20").cargo_metadata(link).probe(name) {
+            Ok(library) => return library.include_paths,
+            Err(e) => errors.push(format!("{}: {}", name, e)),
+        }
+    }
+    panic!(
+        "\n\nLibRaw was not found.\n\
+         Install the LibRaw development package so pkg-config can find it \
+         (e.g. `apt install libraw-dev`, `dnf install LibRaw-devel`, `brew install libraw`),\n\
+         or point the build at a LibRaw installation with\n  \
+         LIBRAW_DIR=<root containing lib/ and libraw/libraw.h or include/libraw/libraw.h>\n\
+         or\n  LIBRAW_LIB_DIR=<directory with the library> LIBRAW_INCLUDE_DIR=<directory containing libraw/libraw.h>\n\n\
+         pkg-config errors:\n{}\n",
+        errors.join("\n")
+    );
+}
+
+/// Uses LIBRAW_DIR, LIBRAW_LIB_DIR and LIBRAW_INCLUDE_DIR if any of them is set.
+fn from_env(link: bool) -> Option<Vec<PathBuf>> {
+    let root = env::var_os("LIBRAW_DIR").map(PathBuf::from);
+    let lib_dir = env::var_os("LIBRAW_LIB_DIR").map(PathBuf::from);
+    if root.is_none() && lib_dir.is_none() {
+        return None;
+    }
+    let lib_dir = lib_dir.or_else(|| root.as_ref().map(|root| root.join("lib"))).unwrap();
+    let include_candidates: Vec<PathBuf> = match env::var_os("LIBRAW_INCLUDE_DIR") {
+        Some(dir) => vec![PathBuf::from(dir)],
+        // The source distribution keeps the headers in <root>/libraw, an install in <root>/include/libraw.
+        None => match &root {
+            Some(root) => vec![root.clone(), root.join("include")],
+            None => lib_dir.parent().map(|parent| vec![parent.join("include")]).unwrap_or_default(),
+        },
+    };
+    let include_dir = include_candidates
+        .iter()
+        .find(|dir| dir.join("libraw").join("libraw.h").is_file())
+        .unwrap_or_else(|| {
+            panic!(
+                "\n\nlibraw/libraw.h was not found in {:?}.\nSet LIBRAW_INCLUDE_DIR to the directory that contains libraw/libraw.h.\n",
+                include_candidates
+            )
+        })
+        .clone();
+
+    if link {
+        println!("cargo:rustc-link-search=native={}", lib_dir.display());
+        println!("cargo:rustc-link-lib=dylib={}", library_name(&lib_dir));
+    }
+    Some(vec![include_dir])
+}
+
+/// The name to pass to the linker for the LibRaw library in `lib_dir`.
+fn library_name(lib_dir: &Path) -> &'static str {
+    if env::var("CARGO_CFG_TARGET_OS").as_deref() == Ok("windows") {
+        // The Windows distribution ships libraw.lib / libraw.dll.
+        return "libraw";
+    }
+    let has_reentrant = ["so", "dylib", "a"]
+        .iter()
+        .any(|ext| lib_dir.join(format!("libraw_r.{}", ext)).exists());
+    if has_reentrant { "raw_r" } else { "raw" }
 }
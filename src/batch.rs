@@ -0,0 +1,285 @@
+//! Converting many raw files at once on a pool of worker threads.
+//!
+//! Every worker decodes its own file, so with LibRaw each thread works on a separate LibRaw
+//! instance; LibRaw is safe to use from several threads as long as instances are not shared.
+
+use crate::decoder::{DecodeOptions, RawDecoder};
+use crate::error::DecodeError;
+use crate::export::{export_image, ExportSettings};
+use crate::progress::Progress;
+use crate::source::RawSource;
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+
+/// What to do with every file of a batch.
+#[derive(Debug, Clone)]
+pub struct BatchSettings {
+    pub options: DecodeOptions,
+    pub export: ExportSettings,
+    /// Where to write; `None` writes next to each input.
+    pub out_dir: Option<PathBuf>,
+    /// Whether existing files may be replaced; otherwise their inputs fail.
+    pub overwrite: bool,
+    pub workers: usize,
+}
+
+/// How one file of a batch went.
+#[derive(Debug, Clone)]
+pub enum Outcome {
+    Converted,
+    Failed(String),
+    /// The batch was cancelled while the file was being decoded.
+    Cancelled,
+}
+
+#[derive(Debug, Clone)]
+pub struct FileResult {
+    pub input: PathBuf,
+    pub output: PathBuf,
+    pub outcome: Outcome,
+}
+
+/// A running (or finished) batch.
+pub struct BatchJob {
+    total: usize,
+    results: Vec<FileResult>,
+    receiver: Receiver<FileResult>,
+    /// Cancels the batch; every file is decoded with its own progress `linked` to it.
+    cancel: Progress,
+    /// The progress of the file each worker is decoding, if any.
+    active: Arc<[Mutex<Option<Progress>>]>,
+    running: bool,
+}
+
+impl BatchJob {
+    /// Starts converting `files` with `settings.workers` threads.
+    pub fn start(files: Vec<PathBuf>, settings: BatchSettings, decoder: Arc<dyn RawDecoder>) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let cancel = Progress::new();
+        let total = files.len();
+        let outputs = output_paths(&files, settings.out_dir.as_deref(), settings.export.format.extension());
+        let jobs: Arc<[(PathBuf, PathBuf)]> = files.into_iter().zip(outputs).collect();
+        let settings = Arc::new(settings);
+        let next = Arc::new(AtomicUsize::new(0));
+        let workers = settings.workers.clamp(1, total.max(1));
+        let active: Arc<[Mutex<Option<Progress>>]> = (0..workers).map(|_| Mutex::new(None)).collect();
+        for worker in 0..workers {
+            let (jobs, settings, next, active) = (jobs.clone(), settings.clone(), next.clone(), active.clone());
+            let (decoder, cancel, sender) = (decoder.clone(), cancel.clone(), sender.clone());
+            thread::spawn(move || {
+                while !cancel.is_cancelled() {
+                    let Some((input, output)) = jobs.get(next.fetch_add(1, Ordering::Relaxed)) else {
+                        return;
+                    };
+                    let progress = cancel.linked();
+                    *lock(&active[worker]) = Some(progress.clone());
+                    let result = convert(decoder.as_ref(), input, output, &settings, &progress);
+                    *lock(&active[worker]) = None;
+                    if sender.send(result).is_err() {
+                        return;
+                    }
+                }
+            });
+        }
+        Self { total, results: Vec::new(), receiver, cancel, active, running: true }
+    }
+
+    /// Collects the files finished since the last call.
+    pub fn poll(&mut self) {
+        loop {
+            match self.receiver.try_recv() {
+                Ok(result) => self.results.push(result),
+                Err(TryRecvError::Empty) => return,
+                Err(TryRecvError::Disconnected) => {
+                    self.running = false;
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Blocks until the next file is finished; `None` once all workers have stopped.
+    pub fn wait(&mut self) -> Option<&FileResult> {
+        match self.receiver.recv() {
+            Ok(result) => {
+                self.results.push(result);
+                self.results.last()
+            }
+            Err(_) => {
+                self.running = false;
+                None
+            }
+        }
+    }
+
+    /// Stops the batch; files being decoded are abandoned and the rest are not started.
+    pub fn cancel(&self) {
+        self.cancel.cancel();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.is_cancelled()
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running
+    }
+
+    pub fn results(&self) -> &[FileResult] {
+        &self.results
+    }
+
+    pub fn total(&self) -> usize {
+        self.total
+    }
+
+    /// Finished files, plus how far the files being decoded are, out of all files; between 0 and 1.
+    pub fn fraction(&self) -> f32 {
+        if self.total == 0 {
+            return 1.0;
+        }
+        let active: f32 = self.active.iter().filter_map(|slot| lock(slot).as_ref().map(Progress::fraction)).sum();
+        ((self.results.len() as f32 + active) / self.total as f32).min(1.0)
+    }
+
+    /// Converted, failed and not converted (cancelled or never started) file counts.
+    pub fn counts(&self) -> (usize, usize, usize) {
+        let converted = self.results.iter().filter(|result| matches!(result.outcome, Outcome::Converted)).count();
+        let failed = self.results.iter().filter(|result| matches!(result.outcome, Outcome::Failed(_))).count();
+        (converted, failed, self.total - converted - failed)
+    }
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn convert(
+    decoder: &dyn RawDecoder,
+    input: &Path,
+    output: &Path,
+    settings: &BatchSettings,
+    progress: &Progress,
+) -> FileResult {
+    let outcome = if !settings.overwrite && output.exists() {
+        Outcome::Failed(format!("{} already exists", output.display()))
+    } else {
+        let source = RawSource::from_path(input);
+        match convert_file(decoder, &source, output, &settings.options, &settings.export, progress) {
+            Ok(()) => Outcome::Converted,
+            Err(ConvertError::Decode(e)) if e.is_cancelled() => Outcome::Cancelled,
+            Err(e) => Outcome::Failed(e.to_string()),
+        }
+    };
+    FileResult { input: input.to_path_buf(), output: output.to_path_buf(), outcome }
+}
+
+/// Why `convert_file` failed.
+#[derive(Debug)]
+pub enum ConvertError {
+    Decode(DecodeError),
+    Save(String),
+}
+
+impl std::fmt::Display for ConvertError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConvertError::Decode(e) => write!(f, "decoding failed: {}", e),
+            ConvertError::Save(e) => write!(f, "saving failed: {}", e),
+        }
+    }
+}
+
+/// Decodes `source` and exports it to `output`.
+pub fn convert_file(
+    decoder: &dyn RawDecoder,
+    source: &RawSource,
+    output: &Path,
+    options: &DecodeOptions,
+    export: &ExportSettings,
+    progress: &Progress,
+) -> Result<(), ConvertError> {
+    let decoded = decoder.decode(source, options, progress).map_err(ConvertError::Decode)?;
+    export_image(&decoded.image, output, export).map_err(ConvertError::Save)
+}
+
+/// Where to write the conversion of `input`: its file stem plus `suffix`, with `extension`,
+/// in `dir` or else next to `input` (e.g. `IMG_0001_thumb.jpg`).
+pub fn output_path(input: &Path, dir: Option<&Path>, suffix: &str, extension: &str) -> PathBuf {
+    let mut name: OsString = input.file_stem().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    name.push(".");
+    name.push(extension);
+    match dir {
+        Some(dir) => dir.join(name),
+        None => input.with_file_name(name),
+    }
+}
+
+/// `output_path` for every input, with `_2`, `_3`, ... added where two inputs would be written
+/// to the same file (e.g. `a/IMG_1.CR2` and `b/IMG_1.NEF` into one folder). Names are compared
+/// ignoring case, as on Windows and macOS file systems.
+pub fn output_paths(inputs: &[PathBuf], dir: Option<&Path>, extension: &str) -> Vec<PathBuf> {
+    let mut taken = HashSet::new();
+    inputs
+        .iter()
+        .map(|input| {
+            let mut output = output_path(input, dir, "", extension);
+            let mut n = 2;
+            while !taken.insert(output.to_string_lossy().to_lowercase()) {
+                output = output_path(input, dir, &format!("_{}", n), extension);
+                n += 1;
+            }
+            output
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(names: &[&str]) -> Vec<PathBuf> {
+        names.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn output_path_replaces_the_extension() {
+        assert_eq!(output_path(Path::new("shoot/IMG_1.CR2"), None, "", "png"), Path::new("shoot/IMG_1.png"));
+        let in_dir = output_path(Path::new("shoot/IMG_1.CR2"), Some(Path::new("out")), "", "tif");
+        assert_eq!(in_dir, Path::new("out/IMG_1.tif"));
+        assert_eq!(output_path(Path::new("IMG_1.CR2"), None, "_thumb", "jpg"), Path::new("IMG_1_thumb.jpg"));
+        // Only the last extension goes.
+        assert_eq!(output_path(Path::new("IMG.0001.NEF"), None, "", "png"), Path::new("IMG.0001.png"));
+    }
+
+    #[test]
+    fn same_stems_from_different_folders_get_unique_names() {
+        let outputs = output_paths(&paths(&["a/IMG_1.CR2", "b/IMG_1.NEF"]), Some(Path::new("out")), "png");
+        assert_eq!(outputs, paths(&["out/IMG_1.png", "out/IMG_1_2.png"]));
+    }
+
+    #[test]
+    fn names_differing_only_in_case_collide() {
+        let outputs = output_paths(&paths(&["a/IMG_1.CR2", "b/img_1.nef"]), Some(Path::new("out")), "png");
+        assert_eq!(outputs, paths(&["out/IMG_1.png", "out/img_1_2.png"]));
+    }
+
+    #[test]
+    fn suffixed_names_do_not_collide_with_real_ones() {
+        let inputs = paths(&["a/X.CR2", "b/X.CR2", "c/X_2.CR2", "d/X.CR2"]);
+        let outputs = output_paths(&inputs, Some(Path::new("out")), "png");
+        assert_eq!(outputs, paths(&["out/X.png", "out/X_2.png", "out/X_2_2.png", "out/X_3.png"]));
+    }
+
+    #[test]
+    fn outputs_next_to_the_inputs_only_collide_within_a_folder() {
+        let outputs = output_paths(&paths(&["a/IMG_1.CR2", "b/IMG_1.CR2", "a/IMG_1.NEF"]), None, "png");
+        assert_eq!(outputs, paths(&["a/IMG_1.png", "b/IMG_1.png", "a/IMG_1_2.png"]));
+    }
+}
@@ -0,0 +1,232 @@
+//! The batch conversion window.
+
+use crate::batch::{BatchJob, BatchSettings, Outcome};
+use crate::decoder::{DecodeMode, DecodeOptions, RawDecoder};
+use crate::export::ExportSettings;
+use crate::export_window::settings_ui;
+use crate::folder::{list_raw_files, Folder};
+use crate::formats::RAW_EXTENSIONS;
+use crate::params::ProcessingParams;
+use eframe::egui;
+use rfd::FileDialog;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+/// Files to convert, output settings, and the running batch if any.
+pub struct BatchWindow {
+    pub open: bool,
+    files: Vec<PathBuf>,
+    out_dir: Option<PathBuf>,
+    overwrite: bool,
+    export: ExportSettings,
+    mode: DecodeMode,
+    workers: usize,
+    job: Option<BatchJob>,
+    error: Option<String>,
+}
+
+impl Default for BatchWindow {
+    fn default() -> Self {
+        Self {
+            open: false,
+            files: Vec::new(),
+            out_dir: None,
+            overwrite: false,
+            export: ExportSettings::default(),
+            mode: DecodeMode::Full,
+            workers: max_workers(),
+            job: None,
+            error: None,
+        }
+    }
+}
+
+impl BatchWindow {
+    /// Draws the window while it is open. Batches are decoded with `decoder` and the
+    /// processing parameters in effect when they start; `folder` offers its files as input.
+    pub fn show(
+        &mut self,
+        ctx: &egui::Context,
+        decoder: &Arc<dyn RawDecoder>,
+        params: &ProcessingParams,
+        folder: Option<&Folder>,
+    ) {
+        if let Some(job) = &mut self.job {
+            job.poll();
+            if job.is_running() {
+                ctx.request_repaint_after(Duration::from_millis(100));
+            }
+        }
+        let mut open = self.open;
+        egui::Window::new("Batch convert").open(&mut open).default_width(420.0).show(ctx, |ui| {
+            let running = self.job.as_ref().is_some_and(BatchJob::is_running);
+            ui.add_enabled_ui(!running, |ui| self.settings(ui, decoder, params, folder));
+            ui.separator();
+            self.status(ui);
+        });
+        self.open = open;
+    }
+
+    fn settings(
+        &mut self,
+        ui: &mut egui::Ui,
+        decoder: &Arc<dyn RawDecoder>,
+        params: &ProcessingParams,
+        folder: Option<&Folder>,
+    ) {
+        ui.horizontal(|ui| {
+            ui.label(format!("{} files", self.files.len()));
+            if ui.button("Add files").clicked() {
+                if let Some(files) = FileDialog::new().add_filter("Raw images", RAW_EXTENSIONS).pick_files() {
+                    self.add(files);
+                }
+            }
+            if ui.button("Add folder").clicked() {
+                if let Some(dir) = FileDialog::new().pick_folder() {
+                    match list_raw_files(&dir) {
+                        Ok(mut files) => {
+                            files.sort();
+                            self.add(files);
+                        }
+                        Err(e) => self.error = Some(format!("Error reading {}: {}", dir.display(), e)),
+                    }
+                }
+            }
+            if let Some(folder) = folder {
+                if ui.button("Add current folder").clicked() {
+                    self.add(folder.files().to_vec());
+                }
+            }
+            if ui.button("Clear").clicked() {
+                self.files.clear();
+                self.job = None;
+            }
+        });
+        egui::ScrollArea::vertical().id_source("batch_files").max_height(120.0).show(ui, |ui| {
+            for file in &self.files {
+                ui.label(file.file_name().unwrap_or_default().to_string_lossy());
+            }
+        });
+        ui.separator();
+        egui::Grid::new("batch_settings").num_columns(2).show(ui, |ui| {
+            ui.label("Output folder");
+            ui.horizontal(|ui| {
+                let label = match &self.out_dir {
+                    Some(dir) => dir.display().to_string(),
+                    None => "Next to the originals".into(),
+                };
+                ui.label(label);
+                if ui.button("Choose").clicked() {
+                    if let Some(dir) = FileDialog::new().pick_folder() {
+                        self.out_dir = Some(dir);
+                    }
+                }
+                if self.out_dir.is_some() && ui.button("Reset").clicked() {
+                    self.out_dir = None;
+                }
+            });
+            ui.end_row();
+
+            ui.label("Existing files");
+            ui.checkbox(&mut self.overwrite, "Overwrite");
+            ui.end_row();
+
+            ui.label("Render");
+            ui.horizontal(|ui| {
+                for mode in DecodeMode::ALL {
+                    ui.selectable_value(&mut self.mode, mode, mode.label());
+                }
+            });
+            ui.end_row();
+
+            ui.label("Workers");
+            ui.add(egui::Slider::new(&mut self.workers, 1..=max_workers()));
+            ui.end_row();
+        });
+        ui.separator();
+        settings_ui(ui, &mut self.export);
+        ui.separator();
+        ui.label("Uses the settings of the Processing panel and the selected backend.");
+        if ui.add_enabled(!self.files.is_empty(), egui::Button::new("Start")).clicked() {
+            self.start(decoder, params);
+        }
+    }
+
+    fn add(&mut self, files: Vec<PathBuf>) {
+        for file in files {
+            if !self.files.contains(&file) {
+                self.files.push(file);
+            }
+        }
+    }
+
+    fn start(&mut self, decoder: &Arc<dyn RawDecoder>, params: &ProcessingParams) {
+        self.error = None;
+        if let Some(dir) = &self.out_dir {
+            if let Err(e) = std::fs::create_dir_all(dir) {
+                self.error = Some(format!("Error creating {}: {}", dir.display(), e));
+                return;
+            }
+        }
+        let settings = BatchSettings {
+            options: DecodeOptions {
+                mode: self.mode,
+                depth: self.export.decode_depth(),
+                params: params.clone(),
+                auto_orient: true,
+            },
+            export: self.export.clone(),
+            out_dir: self.out_dir.clone(),
+            overwrite: self.overwrite,
+            workers: self.workers,
+        };
+        self.job = Some(BatchJob::start(self.files.clone(), settings, decoder.clone()));
+    }
+
+    /// Progress, the cancel button and the per-file results.
+    fn status(&self, ui: &mut egui::Ui) {
+        if let Some(error) = &self.error {
+            ui.colored_label(ui.visuals().error_fg_color, error);
+        }
+        let Some(job) = &self.job else {
+            return;
+        };
+        let (converted, failed, not_converted) = job.counts();
+        ui.horizontal(|ui| {
+            if job.is_running() {
+                let text = format!("{} / {}", job.results().len(), job.total());
+                ui.add(egui::ProgressBar::new(job.fraction()).text(text).desired_width(240.0));
+                if ui.add_enabled(!job.is_cancelled(), egui::Button::new("Cancel")).clicked() {
+                    job.cancel();
+                }
+            } else {
+                ui.label(format!("{} converted, {} failed, {} not converted", converted, failed, not_converted));
+            }
+        });
+        egui::ScrollArea::vertical().id_source("batch_results").max_height(200.0).show(ui, |ui| {
+            egui::Grid::new("batch_results_grid").num_columns(2).striped(true).show(ui, |ui| {
+                for result in job.results() {
+                    ui.label(result.input.file_name().unwrap_or_default().to_string_lossy());
+                    match &result.outcome {
+                        Outcome::Converted => {
+                            ui.label("Done").on_hover_text(result.output.display().to_string());
+                        }
+                        Outcome::Failed(e) => {
+                            ui.colored_label(ui.visuals().error_fg_color, "Failed").on_hover_text(e);
+                        }
+                        Outcome::Cancelled => {
+                            ui.colored_label(ui.visuals().warn_fg_color, "Cancelled");
+                        }
+                    }
+                    ui.end_row();
+                }
+            });
+        });
+    }
+}
+
+fn max_workers() -> usize {
+    thread::available_parallelism().map(|n| n.get()).unwrap_or(4)
+}
@@ -0,0 +1,505 @@
+//! A persistent cache of previews, thumbnails and metadata under the user's cache directory.
+//!
+//! Entries are keyed by the source file's absolute path, size and modification time, plus the
+//! backend (and its version) that produced them, so edited files and library upgrades never
+//! serve stale results. Once the cache outgrows its limit the least recently used entries go.
+
+use crate::decoder::{DecodeMode, DecodeOptions, DecodedImage, RawDecoder};
+use crate::error::DecodeError;
+use crate::metadata::{GpsInfo, ImageMetadata};
+use crate::progress::Progress;
+use crate::source::RawSource;
+use image::codecs::jpeg::JpegEncoder;
+use image::{DynamicImage, ImageFormat, RgbImage};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Used unless LIBRAW_VIEWER_CACHE_MB says otherwise.
+pub const DEFAULT_MAX_BYTES: u64 = 1024 * 1024 * 1024;
+/// Bumped whenever the format of stored entries changes, which invalidates old ones.
+const FORMAT_VERSION: u32 = 1;
+/// Previews are re-encoded at a quality where the loss is invisible next to the camera's own JPEG.
+const PREVIEW_QUALITY: u8 = 95;
+const THUMBNAIL_QUALITY: u8 = 85;
+/// Suffix of partially written entries; they are renamed into place when complete.
+const TEMP_SUFFIX: &str = ".tmp";
+
+/// What an entry holds; each kind is stored in its own file.
+#[derive(Debug, Clone, Copy)]
+enum EntryKind {
+    Thumbnail,
+    Preview,
+    Metadata,
+}
+
+impl EntryKind {
+    fn extension(&self) -> &'static str {
+        match self {
+            EntryKind::Thumbnail => "thumb.jpg",
+            EntryKind::Preview => "preview.jpg",
+            EntryKind::Metadata => "meta",
+        }
+    }
+}
+
+struct Entry {
+    size: u64,
+    last_used: SystemTime,
+}
+
+#[derive(Default)]
+struct Index {
+    entries: HashMap<String, Entry>,
+    total: u64,
+}
+
+/// The on-disk cache. It is shared between the UI and worker threads.
+pub struct DiskCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    /// Every entry on disk, so eviction does not have to scan the directory.
+    index: Mutex<Index>,
+}
+
+impl DiskCache {
+    /// `libraw_viewer` under the platform cache directory (`$XDG_CACHE_HOME` or `~/.cache` on Linux).
+    pub fn default_dir() -> Option<PathBuf> {
+        dirs::cache_dir().map(|dir| dir.join("libraw_viewer"))
+    }
+
+    /// The size limit from LIBRAW_VIEWER_CACHE_MB, or `DEFAULT_MAX_BYTES`. Zero disables the cache.
+    pub fn max_bytes_from_env() -> u64 {
+        std::env::var("LIBRAW_VIEWER_CACHE_MB")
+            .ok()
+            .and_then(|mb| mb.trim().parse::<u64>().ok())
+            .map_or(DEFAULT_MAX_BYTES, |mb| mb.saturating_mul(1024 * 1024))
+    }
+
+    /// Opens (and creates) the cache in `dir`, trimming it to `max_bytes`.
+    pub fn open(dir: PathBuf, max_bytes: u64) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        let mut index = Index::default();
+        for entry in fs::read_dir(&dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let metadata = entry.metadata()?;
+            if !metadata.is_file() {
+                continue;
+            }
+            // Left behind by a crash while writing.
+            if name.ends_with(TEMP_SUFFIX) {
+                let _ = fs::remove_file(entry.path());
+                continue;
+            }
+            let last_used = metadata.modified().unwrap_or(UNIX_EPOCH);
+            index.total += metadata.len();
+            index.entries.insert(name, Entry { size: metadata.len(), last_used });
+        }
+        let cache = Self { dir, max_bytes, index: Mutex::new(index) };
+        cache.evict();
+        Ok(cache)
+    }
+
+    /// The total size of all entries in bytes.
+    pub fn size(&self) -> u64 {
+        self.lock().total
+    }
+
+    pub fn max_bytes(&self) -> u64 {
+        self.max_bytes
+    }
+
+    /// Deletes every entry.
+    pub fn clear(&self) -> io::Result<()> {
+        let mut index = self.lock();
+        let mut result = Ok(());
+        for name in index.entries.keys() {
+            match fs::remove_file(self.dir.join(name)) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => result = Err(e),
+                _ => {}
+            }
+        }
+        *index = Index::default();
+        result
+    }
+
+    pub fn load_thumbnail(&self, source: &Path, backend: &str) -> Option<RgbImage> {
+        let data = self.read(&entry_name(source, backend, EntryKind::Thumbnail)?)?;
+        image::load_from_memory_with_format(&data, ImageFormat::Jpeg).ok().map(DynamicImage::into_rgb8)
+    }
+
+    pub fn store_thumbnail(&self, source: &Path, backend: &str, thumbnail: &RgbImage) {
+        if let Some(name) = entry_name(source, backend, EntryKind::Thumbnail) {
+            if let Some(data) = encode_jpeg(&DynamicImage::ImageRgb8(thumbnail.clone()), THUMBNAIL_QUALITY) {
+                self.write(&name, &data);
+            }
+        }
+    }
+
+    /// A cached embedded preview together with the file's metadata.
+    pub fn load_preview(&self, source: &Path, backend: &str) -> Option<DecodedImage> {
+        let metadata = self.load_metadata(source, backend)?;
+        let data = self.read(&entry_name(source, backend, EntryKind::Preview)?)?;
+        let image = image::load_from_memory_with_format(&data, ImageFormat::Jpeg).ok()?;
+        Some(DecodedImage { image: DynamicImage::ImageRgb8(image.into_rgb8()), metadata })
+    }
+
+    /// Stores an embedded preview and the file's metadata.
+    pub fn store_preview(&self, source: &Path, backend: &str, decoded: &DecodedImage) {
+        if let Some(name) = entry_name(source, backend, EntryKind::Preview) {
+            if let Some(data) = encode_jpeg(&decoded.image, PREVIEW_QUALITY) {
+                self.write(&name, &data);
+                self.store_metadata(source, backend, &decoded.metadata);
+            }
+        }
+    }
+
+    pub fn load_metadata(&self, source: &Path, backend: &str) -> Option<ImageMetadata> {
+        let data = self.read(&entry_name(source, backend, EntryKind::Metadata)?)?;
+        decode_metadata(&String::from_utf8(data).ok()?)
+    }
+
+    pub fn store_metadata(&self, source: &Path, backend: &str, metadata: &ImageMetadata) {
+        if let Some(name) = entry_name(source, backend, EntryKind::Metadata) {
+            self.write(&name, encode_metadata(metadata).as_bytes());
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Index> {
+        self.index.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Reads an entry and marks it as used, both in the index and on disk (via its
+    /// modification time), so the LRU order survives restarts.
+    fn read(&self, name: &str) -> Option<Vec<u8>> {
+        let path = self.dir.join(name);
+        let data = fs::read(&path).ok()?;
+        let now = SystemTime::now();
+        if let Some(entry) = self.lock().entries.get_mut(name) {
+            entry.last_used = now;
+        }
+        if let Ok(file) = File::options().write(true).open(&path) {
+            let _ = file.set_modified(now);
+        }
+        Some(data)
+    }
+
+    /// Writes an entry atomically, so concurrent readers never see a partial file.
+    /// Failures are ignored: the cache is an optimization.
+    fn write(&self, name: &str, data: &[u8]) {
+        static COUNTER: AtomicU64 = AtomicU64::new(0);
+        let temp = self.dir.join(format!(
+            "{}.{}.{}{}",
+            name,
+            std::process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed),
+            TEMP_SUFFIX
+        ));
+        if fs::write(&temp, data).and_then(|_| fs::rename(&temp, self.dir.join(name))).is_err() {
+            let _ = fs::remove_file(&temp);
+            return;
+        }
+        {
+            let mut index = self.lock();
+            let entry = Entry { size: data.len() as u64, last_used: SystemTime::now() };
+            if let Some(old) = index.entries.insert(name.to_string(), entry) {
+                index.total -= old.size;
+            }
+            index.total += data.len() as u64;
+        }
+        self.evict();
+    }
+
+    /// Deletes the least recently used entries until the cache fits its limit.
+    fn evict(&self) {
+        let mut index = self.lock();
+        if index.total <= self.max_bytes {
+            return;
+        }
+        let mut by_age: Vec<(SystemTime, String)> =
+            index.entries.iter().map(|(name, entry)| (entry.last_used, name.clone())).collect();
+        by_age.sort();
+        for (_, name) in by_age {
+            if index.total <= self.max_bytes {
+                break;
+            }
+            if let Some(entry) = index.entries.remove(&name) {
+                index.total -= entry.size;
+                let _ = fs::remove_file(self.dir.join(&name));
+            }
+        }
+    }
+}
+
+/// Identifies the backend for cache keys, including its library version.
+pub fn backend_tag(decoder: &dyn RawDecoder) -> String {
+    match decoder.version() {
+        Ok(version) => format!("{} {}", decoder.name(), version),
+        Err(_) => format!("{} (unavailable)", decoder.name()),
+    }
+}
+
+/// The file name of an entry: a hash of everything that determines its content.
+/// `None` if the source file cannot be inspected.
+fn entry_name(source: &Path, backend: &str, kind: EntryKind) -> Option<String> {
+    let absolute = fs::canonicalize(source).ok()?;
+    let metadata = fs::metadata(&absolute).ok()?;
+    let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    let mut hash = FNV_OFFSET;
+    hash = fnv1a(hash, &FORMAT_VERSION.to_le_bytes());
+    hash = fnv1a(hash, absolute.as_os_str().as_encoded_bytes());
+    hash = fnv1a(hash, &metadata.len().to_le_bytes());
+    hash = fnv1a(hash, &modified.as_secs().to_le_bytes());
+    hash = fnv1a(hash, &modified.subsec_nanos().to_le_bytes());
+    hash = fnv1a(hash, backend.as_bytes());
+    Some(format!("{:016x}.{}", hash, kind.extension()))
+}
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+
+/// 64-bit FNV-1a. Unlike `DefaultHasher` its output is fixed, which keys on disk need.
+fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3))
+}
+
+fn encode_jpeg(image: &DynamicImage, quality: u8) -> Option<Vec<u8>> {
+    let mut data = Vec::new();
+    let rgb = image.to_rgb8();
+    JpegEncoder::new_with_quality(&mut data, quality).encode_image(&rgb).ok()?;
+    Some(data)
+}
+
+/// Serializes metadata as `key=value` lines.
+fn encode_metadata(metadata: &ImageMetadata) -> String {
+    // Values are single-line; a stray newline in a camera string must not break the format.
+    let clean = |value: &str| value.replace(['\n', '\r'], " ");
+    let mut lines = vec![
+        format!("make={}", clean(&metadata.make)),
+        format!("model={}", clean(&metadata.model)),
+        format!("lens={}", clean(&metadata.lens)),
+        format!("serial={}", clean(&metadata.serial)),
+        format!("artist={}", clean(&metadata.artist)),
+        format!("iso={}", metadata.iso),
+        format!("shutter={}", metadata.shutter),
+        format!("aperture={}", metadata.aperture),
+        format!("focal_length={}", metadata.focal_length),
+        format!("flash_fired={}", metadata.flash_fired),
+        format!("flip={}", metadata.flip),
+    ];
+    if let Some(timestamp) = metadata.timestamp {
+        lines.push(format!("timestamp={}", timestamp));
+    }
+    if let Some(gps) = metadata.gps {
+        lines.push(format!("gps={},{},{}", gps.latitude, gps.longitude, gps.altitude));
+    }
+    lines.join("\n")
+}
+
+/// Parses `encode_metadata` output; `None` if any value is malformed.
+fn decode_metadata(text: &str) -> Option<ImageMetadata> {
+    let mut metadata = ImageMetadata::default();
+    for line in text.lines() {
+        let (key, value) = line.split_once('=')?;
+        match key {
+            "make" => metadata.make = value.to_string(),
+            "model" => metadata.model = value.to_string(),
+            "lens" => metadata.lens = value.to_string(),
+            "serial" => metadata.serial = value.to_string(),
+            "artist" => metadata.artist = value.to_string(),
+            "iso" => metadata.iso = value.parse().ok()?,
+            "shutter" => metadata.shutter = value.parse().ok()?,
+            "aperture" => metadata.aperture = value.parse().ok()?,
+            "focal_length" => metadata.focal_length = value.parse().ok()?,
+            "flash_fired" => metadata.flash_fired = value.parse().ok()?,
+            "flip" => metadata.flip = value.parse().ok()?,
+            "timestamp" => metadata.timestamp = Some(value.parse().ok()?),
+            "gps" => {
+                let mut parts = value.split(',');
+                let mut next = || parts.next()?.parse::<f64>().ok();
+                let (latitude, longitude, altitude) = (next()?, next()?, next()?);
+                metadata.gps = Some(GpsInfo { latitude, longitude, altitude: altitude as f32 });
+            }
+            _ => {}
+        }
+    }
+    Some(metadata)
+}
+
+/// Serves embedded previews and metadata from the disk cache, decoding with `inner` on a miss.
+/// Raw renders are not cached: they depend on every processing parameter and are large.
+pub struct CachedDecoder {
+    inner: Arc<dyn RawDecoder>,
+    cache: Arc<DiskCache>,
+}
+
+impl CachedDecoder {
+    pub fn new(inner: Arc<dyn RawDecoder>, cache: Arc<DiskCache>) -> Self {
+        Self { inner, cache }
+    }
+}
+
+impl RawDecoder for CachedDecoder {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    fn version(&self) -> Result<String, DecodeError> {
+        self.inner.version()
+    }
+
+    fn decode(
+        &self,
+        source: &RawSource,
+        options: &DecodeOptions,
+        progress: &Progress,
+    ) -> Result<DecodedImage, DecodeError> {
+        let RawSource::Path(path) = source else {
+            return self.inner.decode(source, options, progress);
+        };
+        if options.mode != DecodeMode::Preview || !options.auto_orient {
+            return self.inner.decode(source, options, progress);
+        }
+        let backend = backend_tag(self.inner.as_ref());
+        if let Some(decoded) = self.cache.load_preview(path, &backend) {
+            return Ok(decoded);
+        }
+        let decoded = self.inner.decode(source, options, progress)?;
+        // Encoding a large preview takes a while; do it without delaying the result.
+        let (cache, path, copy) = (self.cache.clone(), path.clone(), decoded.clone());
+        thread::spawn(move || cache.store_preview(&path, &backend, &copy));
+        Ok(decoded)
+    }
+
+    fn decode_preview(&self, source: &RawSource) -> Result<DynamicImage, DecodeError> {
+        self.inner.decode_preview(source)
+    }
+
+    fn read_metadata(&self, source: &RawSource) -> Result<ImageMetadata, DecodeError> {
+        let RawSource::Path(path) = source else {
+            return self.inner.read_metadata(source);
+        };
+        let backend = backend_tag(self.inner.as_ref());
+        if let Some(metadata) = self.cache.load_metadata(path, &backend) {
+            return Ok(metadata);
+        }
+        let metadata = self.inner.read_metadata(source)?;
+        self.cache.store_metadata(path, &backend, &metadata);
+        Ok(metadata)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh directory for one test, removed by the caller.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("libraw_viewer_cache_{}_{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn entry_names_depend_on_file_backend_and_kind() {
+        let dir = temp_dir("entry_names");
+        let file = dir.join("IMG_0001.CR2");
+        fs::write(&file, b"raw data").unwrap();
+        let name = entry_name(&file, "LibRaw 0.21.3", EntryKind::Preview).unwrap();
+
+        assert_eq!(entry_name(&file, "LibRaw 0.21.3", EntryKind::Preview).unwrap(), name);
+        // Relative and absolute spellings of the same file share entries.
+        let relative = dir.join(".").join("IMG_0001.CR2");
+        assert_eq!(entry_name(&relative, "LibRaw 0.21.3", EntryKind::Preview).unwrap(), name);
+        assert_ne!(entry_name(&file, "LibRaw 0.21.2", EntryKind::Preview).unwrap(), name);
+        assert_ne!(entry_name(&file, "rawloader 0.37", EntryKind::Preview).unwrap(), name);
+        assert!(entry_name(&file, "LibRaw 0.21.3", EntryKind::Thumbnail).unwrap().ends_with(".thumb.jpg"));
+        assert!(name.ends_with(".preview.jpg"));
+
+        // An edited file gets new entries.
+        fs::write(&file, b"edited raw data").unwrap();
+        assert_ne!(entry_name(&file, "LibRaw 0.21.3", EntryKind::Preview).unwrap(), name);
+
+        assert_eq!(entry_name(&dir.join("missing.CR2"), "LibRaw 0.21.3", EntryKind::Preview), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn evicts_least_recently_used_entries() {
+        let dir = temp_dir("eviction");
+        let cache = DiskCache::open(dir.clone(), 250).unwrap();
+        cache.write("a", &[0; 100]);
+        cache.write("b", &[0; 100]);
+        // Reading `a` makes `b` the least recently used entry.
+        assert!(cache.read("a").is_some());
+        cache.write("c", &[0; 100]);
+
+        assert_eq!(cache.size(), 200);
+        assert!(cache.read("a").is_some());
+        assert!(cache.read("b").is_none());
+        assert!(cache.read("c").is_some());
+        assert!(!dir.join("b").exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rewriting_an_entry_replaces_its_size() {
+        let dir = temp_dir("rewrite");
+        let cache = DiskCache::open(dir.clone(), 1000).unwrap();
+        cache.write("a", &[0; 100]);
+        cache.write("a", &[0; 40]);
+        assert_eq!(cache.size(), 40);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn opening_indexes_existing_entries_and_trims_them() {
+        let dir = temp_dir("reopen");
+        {
+            let cache = DiskCache::open(dir.clone(), 1000).unwrap();
+            cache.write("a", &[0; 300]);
+            cache.write("b", &[0; 300]);
+        }
+        fs::write(dir.join(format!("c{}", TEMP_SUFFIX)), [0; 50]).unwrap();
+
+        assert_eq!(DiskCache::open(dir.clone(), 1000).unwrap().size(), 600);
+        // A crash leftover is deleted rather than indexed.
+        assert!(!dir.join(format!("c{}", TEMP_SUFFIX)).exists());
+        let cache = DiskCache::open(dir.clone(), 400).unwrap();
+        assert_eq!(cache.size(), 300);
+        cache.clear().unwrap();
+        assert_eq!(cache.size(), 0);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn metadata_survives_a_round_trip() {
+        let metadata = ImageMetadata {
+            make: "Sony".into(),
+            model: "ILCE-7M3\nwith a newline".into(),
+            iso: 800.0,
+            shutter: 1.0 / 250.0,
+            timestamp: Some(1_600_000_000),
+            gps: Some(GpsInfo { latitude: 48.5, longitude: -2.25, altitude: 12.0 }),
+            flash_fired: true,
+            flip: 6,
+            ..ImageMetadata::default()
+        };
+        let decoded = decode_metadata(&encode_metadata(&metadata)).unwrap();
+        assert_eq!(decoded.model, "ILCE-7M3 with a newline");
+        assert_eq!(decoded.iso, metadata.iso);
+        assert_eq!(decoded.shutter, metadata.shutter);
+        assert_eq!(decoded.timestamp, metadata.timestamp);
+        assert_eq!(decoded.gps.map(|gps| gps.longitude), Some(-2.25));
+        assert!(decoded.flash_fired);
+        assert_eq!(decoded.flip, 6);
+        assert!(decode_metadata("iso=fast").is_none());
+    }
+}
@@ -0,0 +1,608 @@
+//! Command-line arguments, and the subcommands that run without opening a window.
+
+use crate::batch::{convert_file, output_path, BatchJob, BatchSettings, Outcome};
+use crate::decoder::{self, BitDepth, DecodeMode, DecodeOptions, RawDecoder};
+use crate::export::{save_image, ExportFormat, ExportSettings};
+use crate::folder::list_raw_files;
+use crate::params::{ColorSpace, Demosaic, GammaCurve, HighlightMode, WhiteBalance};
+use crate::progress::Progress;
+use crate::source::RawSource;
+use crate::thumbnails::THUMBNAIL_SIDE;
+use std::ffi::OsString;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::thread;
+
+/// The input name that stands for stdin.
+const STDIN: &str = "-";
+
+pub const USAGE: &str = "\
+Usage:
+  libraw_viewer [FILE|DIR]               open a raw file or a folder in the viewer
+  libraw_viewer info FILE...             print the metadata of raw files
+  libraw_viewer thumb [-o OUT] FILE      write the embedded preview, scaled down
+  libraw_viewer convert [-o OUT] FILE    decode a raw file to PNG, TIFF, JPEG, WebP, AVIF or OpenEXR
+  libraw_viewer batch [-o DIR] FILE|DIR...
+                                         convert many files; folders are searched for raw files
+
+A FILE of - reads the raw file from stdin (info, thumb and convert; thumb and convert need -o).
+
+Options:
+  -o, --output PATH      output file (thumb, convert) or folder (batch);
+                         by default next to the input
+  --format EXT           output format of batch, and of convert without -o:
+                         png (default), tif, jpg, webp, avif or exr
+  --quality N            JPEG, WebP and AVIF quality, 1-100 (default 90)
+  --size N               longest side of thumbnails in pixels (default 256)
+  -j, --jobs N           files converted in parallel by batch (default: one per CPU core)
+  --mode MODE            preview, half or full (default full)
+  --16-bit               write 16 bits per channel (PNG and TIFF only)
+  --no-orient            do not rotate to the camera orientation
+  --overwrite            replace existing output files
+  --backend NAME         decoder backend to use, e.g. LibRaw or rawloader
+  -h, --help             print this help
+
+Processing (convert and batch, except in preview mode):
+  --wb WB                white balance: camera (default), auto, daylight,
+                         or R,G,B[,G2] multipliers
+  --demosaic NAME        linear, vng, ppg, ahd (default), dcb, dht or aahd
+  --exposure EV          exposure correction in stops, -2 to 3 (default 0)
+  --highlights MODE      clip (default), unclip, blend, or a rebuild level 3-9
+  --noise N              wavelet denoising threshold (default 0, off)
+  --brightness N         brightness multiplier (default 1)
+  --no-auto-bright       do not brighten the image automatically
+  --gamma CURVE          bt709 (default), srgb, linear, or POWER,SLOPE
+  --color-space NAME     srgb (default), adobe, widegamut, prophoto, xyz, aces,
+                         p3, rec2020 or raw";
+
+/// Names accepted by `--color-space`.
+const COLOR_SPACES: [(&str, ColorSpace); 9] = [
+    ("srgb", ColorSpace::Srgb),
+    ("adobe", ColorSpace::AdobeRgb),
+    ("widegamut", ColorSpace::WideGamut),
+    ("prophoto", ColorSpace::ProPhoto),
+    ("xyz", ColorSpace::Xyz),
+    ("aces", ColorSpace::Aces),
+    ("p3", ColorSpace::DciP3),
+    ("rec2020", ColorSpace::Rec2020),
+    ("raw", ColorSpace::Raw),
+];
+
+/// What to do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Command {
+    /// Start the viewer, optionally opening the one input.
+    Gui,
+    Info,
+    Thumb,
+    Convert,
+    Batch,
+    Help,
+}
+
+impl Command {
+    fn from_name(name: &str) -> Option<Command> {
+        match name {
+            "info" => Some(Command::Info),
+            "thumb" => Some(Command::Thumb),
+            "convert" => Some(Command::Convert),
+            "batch" => Some(Command::Batch),
+            "help" => Some(Command::Help),
+            _ => None,
+        }
+    }
+}
+
+/// Parsed command line.
+#[derive(Debug, Clone)]
+pub struct Args {
+    pub command: Command,
+    pub inputs: Vec<PathBuf>,
+    pub output: Option<PathBuf>,
+    /// How `convert` and `batch` write files; the format is overridden by the extension
+    /// of an explicit `convert` output path.
+    pub export: ExportSettings,
+    pub size: u32,
+    /// Worker threads for `batch`.
+    pub jobs: usize,
+    /// Whether `thumb`, `convert` and `batch` may replace existing files.
+    pub overwrite: bool,
+    pub backend: Option<String>,
+    pub options: DecodeOptions,
+}
+
+impl Args {
+    /// Parses the arguments after the program name.
+    pub fn parse(args: impl IntoIterator<Item = OsString>) -> Result<Args, String> {
+        let mut args = args.into_iter().peekable();
+        let command = match args.peek().and_then(|arg| arg.to_str()).and_then(Command::from_name) {
+            Some(command) => {
+                args.next();
+                command
+            }
+            None => Command::Gui,
+        };
+        let mut parsed = Args {
+            command,
+            inputs: Vec::new(),
+            output: None,
+            export: ExportSettings::default(),
+            size: THUMBNAIL_SIDE,
+            jobs: thread::available_parallelism().map(|n| n.get()).unwrap_or(4),
+            overwrite: false,
+            backend: None,
+            options: DecodeOptions { mode: DecodeMode::Full, ..DecodeOptions::default() },
+        };
+        let mut only_inputs = false;
+        while let Some(arg) = args.next() {
+            let flag = arg.to_str().filter(|flag| !only_inputs && flag.starts_with('-') && *flag != STDIN);
+            let Some(flag) = flag else {
+                parsed.inputs.push(PathBuf::from(arg));
+                continue;
+            };
+            let mut value = || {
+                args.next()
+                    .and_then(|value| value.into_string().ok())
+                    .ok_or_else(|| format!("{} needs a value", flag))
+            };
+            match flag {
+                "--" => only_inputs = true,
+                "-h" | "--help" => parsed.command = Command::Help,
+                "-o" | "--output" => parsed.output = Some(PathBuf::from(value()?)),
+                "--format" => {
+                    let format = value()?;
+                    parsed.export.format = ExportFormat::from_extension(format.trim_start_matches('.'))
+                        .ok_or_else(|| format!("unsupported format: {}", format))?;
+                }
+                "--quality" => {
+                    let quality = value()?;
+                    parsed.export.quality = quality.parse().ok().filter(|quality| (1..=100).contains(quality)).ok_or_else(
+                        || format!("invalid quality: {} (expected 1-100)", quality),
+                    )?;
+                }
+                "--size" => {
+                    let size = value()?;
+                    parsed.size = size.parse().ok().filter(|size| *size > 0).ok_or_else(|| {
+                        format!("invalid size: {}", size)
+                    })?;
+                }
+                "-j" | "--jobs" => {
+                    let jobs = value()?;
+                    parsed.jobs = jobs.parse().ok().filter(|jobs| *jobs > 0).ok_or_else(|| {
+                        format!("invalid number of jobs: {}", jobs)
+                    })?;
+                }
+                "--mode" => {
+                    parsed.options.mode = match value()?.as_str() {
+                        "preview" => DecodeMode::Preview,
+                        "half" => DecodeMode::HalfSize,
+                        "full" => DecodeMode::Full,
+                        other => return Err(format!("unknown mode: {} (expected preview, half or full)", other)),
+                    };
+                }
+                "--16-bit" => parsed.export.depth = BitDepth::Sixteen,
+                "--no-orient" => parsed.options.auto_orient = false,
+                "--overwrite" => parsed.overwrite = true,
+                "--wb" => parsed.options.params.white_balance = parse_white_balance(&value()?)?,
+                "--demosaic" => {
+                    let name = value()?;
+                    parsed.options.params.demosaic = Demosaic::ALL
+                        .into_iter()
+                        .find(|demosaic| demosaic.label().eq_ignore_ascii_case(&name))
+                        .ok_or_else(|| format!("unknown demosaic algorithm: {}", name))?;
+                }
+                "--exposure" => {
+                    let exposure = value()?;
+                    parsed.options.params.exposure_ev =
+                        exposure.parse().ok().filter(|ev| (-2.0..=3.0).contains(ev)).ok_or_else(|| {
+                            format!("invalid exposure: {} (expected -2 to 3 stops)", exposure)
+                        })?;
+                }
+                "--highlights" => {
+                    parsed.options.params.highlight = match value()?.as_str() {
+                        "clip" => HighlightMode::Clip,
+                        "unclip" => HighlightMode::Unclip,
+                        "blend" => HighlightMode::Blend,
+                        other => other
+                            .parse()
+                            .ok()
+                            .filter(|level| (3..=9).contains(level))
+                            .map(HighlightMode::Rebuild)
+                            .ok_or_else(|| {
+                                format!("unknown highlight mode: {} (expected clip, unclip, blend or 3-9)", other)
+                            })?,
+                    };
+                }
+                "--noise" => {
+                    let noise = value()?;
+                    parsed.options.params.noise_threshold = noise
+                        .parse()
+                        .ok()
+                        .filter(|threshold: &f32| *threshold >= 0.0)
+                        .ok_or_else(|| format!("invalid noise threshold: {}", noise))?;
+                }
+                "--brightness" => {
+                    let brightness = value()?;
+                    parsed.options.params.brightness = brightness
+                        .parse()
+                        .ok()
+                        .filter(|brightness: &f32| *brightness > 0.0)
+                        .ok_or_else(|| format!("invalid brightness: {}", brightness))?;
+                }
+                "--no-auto-bright" => parsed.options.params.auto_bright = false,
+                "--gamma" => parsed.options.params.gamma = parse_gamma(&value()?)?,
+                "--color-space" => {
+                    let name = value()?;
+                    parsed.options.params.color_space = COLOR_SPACES
+                        .iter()
+                        .find(|(known, _)| known.eq_ignore_ascii_case(&name))
+                        .map(|(_, color_space)| *color_space)
+                        .ok_or_else(|| format!("unknown color space: {}", name))?;
+                }
+                "--backend" => parsed.backend = Some(value()?),
+                _ => return Err(format!("unknown option: {}", flag)),
+            }
+        }
+        parsed.check()?;
+        Ok(parsed)
+    }
+
+    fn check(&self) -> Result<(), String> {
+        let count = self.inputs.len();
+        match self.command {
+            Command::Gui if count > 1 => Err("the viewer opens a single file or folder".into()),
+            Command::Info | Command::Batch if count == 0 => Err("no input files".into()),
+            Command::Thumb | Command::Convert if count != 1 => Err("expected exactly one input file".into()),
+            Command::Gui | Command::Batch if self.inputs.iter().any(|input| input == Path::new(STDIN)) => {
+                Err("only info, thumb and convert can read from stdin".into())
+            }
+            Command::Thumb | Command::Convert if self.inputs[0] == Path::new(STDIN) && self.output.is_none() => {
+                Err("reading from stdin needs an output path (-o)".into())
+            }
+            Command::Convert | Command::Batch if !self.export.format.is_available() => {
+                Err(format!("{} export is not available in this build", self.export.format.label()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// `camera`, `auto`, `daylight`, or `R,G,B[,G2]` multipliers (G2 defaults to G).
+fn parse_white_balance(value: &str) -> Result<WhiteBalance, String> {
+    match value {
+        "camera" => Ok(WhiteBalance::Camera),
+        "auto" => Ok(WhiteBalance::Auto),
+        "daylight" => Ok(WhiteBalance::Daylight),
+        _ => match parse_numbers(value).as_deref() {
+            Some(&[r, g, b]) => Ok(WhiteBalance::Custom([r, g, b, g])),
+            Some(&[r, g, b, g2]) => Ok(WhiteBalance::Custom([r, g, b, g2])),
+            _ => Err(format!("invalid white balance: {} (expected camera, auto, daylight or R,G,B)", value)),
+        },
+    }
+}
+
+/// `bt709`, `srgb`, `linear`, or `POWER,SLOPE` (e.g. `2.4,12.92`).
+fn parse_gamma(value: &str) -> Result<GammaCurve, String> {
+    match value {
+        "bt709" => Ok(GammaCurve::Bt709),
+        "srgb" => Ok(GammaCurve::Srgb),
+        "linear" => Ok(GammaCurve::Linear),
+        _ => match parse_numbers(value).as_deref() {
+            Some(&[power, slope]) if power > 0.0 => {
+                Ok(GammaCurve::Custom { power: power as f64, slope: slope as f64 })
+            }
+            _ => Err(format!("invalid gamma: {} (expected bt709, srgb, linear or POWER,SLOPE)", value)),
+        },
+    }
+}
+
+/// Comma-separated non-negative numbers.
+fn parse_numbers(value: &str) -> Option<Vec<f32>> {
+    value.split(',').map(|number| number.trim().parse().ok().filter(|number: &f32| *number >= 0.0)).collect()
+}
+
+/// Fails if `output` exists and may not be replaced.
+fn check_output(output: &Path, overwrite: bool) -> Result<(), String> {
+    if !overwrite && output.exists() {
+        return Err(format!("{} already exists (use --overwrite to replace it)", output.display()));
+    }
+    Ok(())
+}
+
+/// Runs a headless command and reports errors on stderr.
+pub fn run(args: &Args) -> ExitCode {
+    if args.command == Command::Help {
+        println!("{}", USAGE);
+        return ExitCode::SUCCESS;
+    }
+    let result = pick_backend(args.backend.as_deref()).and_then(|decoder| match args.command {
+        Command::Info => info(args, decoder.as_ref()),
+        Command::Thumb => thumb(args, decoder.as_ref()),
+        Command::Convert => convert(args, decoder.as_ref()),
+        Command::Batch => run_batch(args, decoder),
+        Command::Gui | Command::Help => unreachable!("handled by the caller"),
+    });
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// The backend called `name` (case-insensitively), or the preferred one.
+fn pick_backend(name: Option<&str>) -> Result<Arc<dyn RawDecoder>, String> {
+    let backends = decoder::backends();
+    match name {
+        None => Ok(backends.into_iter().next().expect("at least one decoder backend")),
+        Some(name) => {
+            let names: Vec<&str> = backends.iter().map(|backend| backend.name()).collect();
+            let unknown = format!("unknown backend: {} (available: {})", name, names.join(", "));
+            backends.into_iter().find(|backend| backend.name().eq_ignore_ascii_case(name)).ok_or(unknown)
+        }
+    }
+}
+
+/// Prints the metadata of every input; files that cannot be read are reported and skipped.
+fn info(args: &Args, decoder: &dyn RawDecoder) -> Result<(), String> {
+    let mut failed = 0;
+    for (i, path) in args.inputs.iter().enumerate() {
+        if args.inputs.len() > 1 {
+            if i > 0 {
+                println!();
+            }
+            println!("{}", path.display());
+        }
+        match open_input(path).and_then(|source| {
+            decoder.read_metadata(&source).map_err(|e| format!("Error reading {}: {}", path.display(), e))
+        }) {
+            Ok(metadata) => {
+                for (label, value) in metadata.fields() {
+                    println!("{}: {}", label, value);
+                }
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                failed += 1;
+            }
+        }
+    }
+    match failed {
+        0 => Ok(()),
+        _ => Err(format!("{} of {} files could not be read", failed, args.inputs.len())),
+    }
+}
+
+fn thumb(args: &Args, decoder: &dyn RawDecoder) -> Result<(), String> {
+    let input = &args.inputs[0];
+    let output = args.output.clone().unwrap_or_else(|| output_path(input, None, "_thumb", "jpg"));
+    check_output(&output, args.overwrite)?;
+    let preview = decoder
+        .decode_preview(&open_input(input)?)
+        .map_err(|e| format!("Error decoding {}: {}", input.display(), e))?;
+    let thumbnail = preview.thumbnail(args.size, args.size);
+    save_image(&thumbnail, &output).map_err(|e| format!("Error saving {}: {}", output.display(), e))?;
+    println!("{} -> {}", input.display(), output.display());
+    Ok(())
+}
+
+fn convert(args: &Args, decoder: &dyn RawDecoder) -> Result<(), String> {
+    let input = &args.inputs[0];
+    let mut export = args.export.clone();
+    let output = match &args.output {
+        Some(output) => {
+            let extension = output.extension().unwrap_or_default().to_string_lossy();
+            export.format = ExportFormat::from_extension(&extension)
+                .ok_or_else(|| format!("unsupported output extension: \"{}\"", extension))?;
+            output.clone()
+        }
+        None => output_path(input, None, "", export.format.extension()),
+    };
+    check_output(&output, args.overwrite)?;
+    let options = DecodeOptions { depth: export.decode_depth(), ..args.options.clone() };
+    convert_file(decoder, &open_input(input)?, &output, &options, &export, &Progress::new())
+        .map_err(|e| format!("Error converting {}: {}", input.display(), e))?;
+    println!("{} -> {}", input.display(), output.display());
+    Ok(())
+}
+
+/// The raw file `path` names; `-` reads it from stdin.
+fn open_input(path: &Path) -> Result<RawSource, String> {
+    if path != Path::new(STDIN) {
+        return Ok(RawSource::from_path(path));
+    }
+    RawSource::from_reader(io::stdin().lock()).map_err(|e| format!("Error reading stdin: {}", e))
+}
+
+/// Converts every input file, and every raw file in input folders, in parallel.
+/// A failure does not stop the rest.
+fn run_batch(args: &Args, decoder: Arc<dyn RawDecoder>) -> Result<(), String> {
+    let mut files = Vec::new();
+    for input in &args.inputs {
+        if input.is_dir() {
+            let mut found = list_raw_files(input).map_err(|e| format!("Error reading {}: {}", input.display(), e))?;
+            found.sort();
+            files.extend(found);
+        } else {
+            files.push(input.clone());
+        }
+    }
+    if let Some(dir) = &args.output {
+        std::fs::create_dir_all(dir).map_err(|e| format!("Error creating {}: {}", dir.display(), e))?;
+    }
+    let settings = BatchSettings {
+        options: DecodeOptions { depth: args.export.decode_depth(), ..args.options.clone() },
+        export: args.export.clone(),
+        out_dir: args.output.clone(),
+        overwrite: args.overwrite,
+        workers: args.jobs,
+    };
+    let mut job = BatchJob::start(files, settings, decoder);
+    while let Some(result) = job.wait() {
+        match &result.outcome {
+            Outcome::Converted => println!("{} -> {}", result.input.display(), result.output.display()),
+            Outcome::Failed(e) => eprintln!("Error converting {}: {}", result.input.display(), e),
+            Outcome::Cancelled => eprintln!("Cancelled {}", result.input.display()),
+        }
+    }
+    let (_, failed, not_converted) = job.counts();
+    match failed + not_converted {
+        0 => Ok(()),
+        missing => Err(format!("{} of {} files were not converted", missing, job.total())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, String> {
+        Args::parse(args.iter().map(OsString::from))
+    }
+
+    #[test]
+    fn no_command_opens_the_viewer() {
+        let args = parse(&[]).unwrap();
+        assert_eq!(args.command, Command::Gui);
+        assert!(args.inputs.is_empty());
+
+        let args = parse(&["IMG_0001.CR2"]).unwrap();
+        assert_eq!(args.command, Command::Gui);
+        assert_eq!(args.inputs, [PathBuf::from("IMG_0001.CR2")]);
+        assert!(parse(&["a.nef", "b.nef"]).is_err());
+    }
+
+    #[test]
+    fn options_are_parsed_around_the_inputs() {
+        let args = parse(&[
+            "convert", "--mode", "half", "IMG.ARW", "-o", "out.tif", "--16-bit", "--no-orient", "--backend",
+            "rawloader",
+        ])
+        .unwrap();
+        assert_eq!(args.command, Command::Convert);
+        assert_eq!(args.inputs, [PathBuf::from("IMG.ARW")]);
+        assert_eq!(args.output, Some(PathBuf::from("out.tif")));
+        assert_eq!(args.options.mode, DecodeMode::HalfSize);
+        assert_eq!(args.export.depth, BitDepth::Sixteen);
+        assert!(!args.options.auto_orient);
+        assert_eq!(args.backend.as_deref(), Some("rawloader"));
+    }
+
+    #[test]
+    fn defaults_decode_the_full_image() {
+        let args = parse(&["thumb", "IMG.ARW"]).unwrap();
+        assert_eq!(args.options.mode, DecodeMode::Full);
+        assert_eq!(args.size, THUMBNAIL_SIDE);
+        assert_eq!(args.export, ExportSettings::default());
+        assert_eq!(args.output, None);
+    }
+
+    #[test]
+    fn export_options_are_checked() {
+        assert_eq!(parse(&["batch", "--format", ".TIF", "dir"]).unwrap().export.format, ExportFormat::Tiff);
+        assert!(parse(&["batch", "--format", "gif", "dir"]).is_err());
+        assert_eq!(parse(&["batch", "--format", "avif", "dir"]).is_ok(), cfg!(feature = "avif"));
+        assert_eq!(parse(&["convert", "--quality", "75", "a.nef"]).unwrap().export.quality, 75);
+        assert!(parse(&["convert", "--quality", "0", "a.nef"]).is_err());
+        assert!(parse(&["convert", "--quality", "101", "a.nef"]).is_err());
+    }
+
+    #[test]
+    fn bad_values_are_rejected() {
+        assert!(parse(&["thumb", "--size", "0", "a.nef"]).is_err());
+        assert!(parse(&["thumb", "--size", "big", "a.nef"]).is_err());
+        assert!(parse(&["convert", "--mode", "quarter", "a.nef"]).is_err());
+        assert!(parse(&["convert", "a.nef", "-o"]).unwrap_err().contains("needs a value"));
+        assert!(parse(&["info", "--verbose", "a.nef"]).unwrap_err().contains("unknown option"));
+    }
+
+    #[test]
+    fn input_counts_are_checked() {
+        assert!(parse(&["info"]).is_err());
+        assert!(parse(&["batch"]).is_err());
+        assert!(parse(&["thumb", "a.nef", "b.nef"]).is_err());
+        assert!(parse(&["convert"]).is_err());
+        assert_eq!(parse(&["info", "a.nef", "b.nef"]).unwrap().inputs.len(), 2);
+    }
+
+    #[test]
+    fn help_wins_over_missing_inputs() {
+        assert_eq!(parse(&["help"]).unwrap().command, Command::Help);
+        assert_eq!(parse(&["convert", "--help"]).unwrap().command, Command::Help);
+    }
+
+    #[test]
+    fn double_dash_ends_the_options() {
+        let args = parse(&["info", "--", "-o", "--mode"]).unwrap();
+        assert_eq!(args.inputs, [PathBuf::from("-o"), PathBuf::from("--mode")]);
+        // A subcommand name after the first argument is a file name.
+        assert_eq!(parse(&["a.nef"]).unwrap().command, Command::Gui);
+        assert_eq!(parse(&["info", "batch"]).unwrap().inputs, [PathBuf::from("batch")]);
+    }
+
+    #[test]
+    fn stdin_is_an_input_for_single_file_commands() {
+        assert_eq!(parse(&["info", "-"]).unwrap().inputs, [PathBuf::from(STDIN)]);
+        assert!(parse(&["convert", "-", "-o", "out.png"]).is_ok());
+        assert!(parse(&["convert", "-"]).unwrap_err().contains("-o"));
+        assert!(parse(&["thumb", "-"]).is_err());
+        assert!(parse(&["batch", "-"]).is_err());
+        assert!(parse(&["-"]).is_err());
+    }
+
+    #[test]
+    fn batch_takes_workers_overwrite_and_processing_flags() {
+        let args = parse(&[
+            "batch", "-j", "3", "--overwrite", "--wb", "2,1,1.5", "--demosaic", "DCB", "--exposure", "-1.5",
+            "--highlights", "5", "--noise", "100", "--brightness", "1.2", "--no-auto-bright", "--gamma", "2.2,4.5",
+            "--color-space", "ProPhoto", "shoot",
+        ])
+        .unwrap();
+        assert_eq!(args.command, Command::Batch);
+        assert_eq!(args.inputs, [PathBuf::from("shoot")]);
+        assert_eq!(args.jobs, 3);
+        assert!(args.overwrite);
+        let params = &args.options.params;
+        assert_eq!(params.white_balance, WhiteBalance::Custom([2.0, 1.0, 1.5, 1.0]));
+        assert_eq!(params.demosaic, Demosaic::Dcb);
+        assert_eq!(params.exposure_ev, -1.5);
+        assert_eq!(params.highlight, HighlightMode::Rebuild(5));
+        assert_eq!(params.noise_threshold, 100.0);
+        assert_eq!(params.brightness, 1.2);
+        assert!(!params.auto_bright);
+        assert_eq!(params.gamma, GammaCurve::Custom { power: 2.2f32 as f64, slope: 4.5 });
+        assert_eq!(params.color_space, ColorSpace::ProPhoto);
+    }
+
+    #[test]
+    fn batch_defaults_keep_existing_files_and_camera_settings() {
+        let args = parse(&["batch", "shoot"]).unwrap();
+        assert!(args.jobs > 0);
+        assert!(!args.overwrite);
+        assert_eq!(args.options.params, Default::default());
+    }
+
+    #[test]
+    fn bad_batch_flags_are_rejected() {
+        assert!(parse(&["batch", "-j", "0", "shoot"]).is_err());
+        assert!(parse(&["batch", "--wb", "1,2", "shoot"]).is_err());
+        assert!(parse(&["batch", "--wb", "1,-2,1", "shoot"]).is_err());
+        assert!(parse(&["batch", "--demosaic", "bilinear", "shoot"]).is_err());
+        assert!(parse(&["batch", "--exposure", "4", "shoot"]).is_err());
+        assert!(parse(&["batch", "--highlights", "2", "shoot"]).is_err());
+        assert!(parse(&["batch", "--brightness", "0", "shoot"]).is_err());
+        assert!(parse(&["batch", "--gamma", "0,4.5", "shoot"]).is_err());
+        assert!(parse(&["batch", "--color-space", "cmyk", "shoot"]).is_err());
+    }
+
+    #[test]
+    fn existing_outputs_need_overwrite() {
+        let existing = std::env::current_exe().unwrap();
+        assert!(check_output(&existing, false).unwrap_err().contains("--overwrite"));
+        assert!(check_output(&existing, true).is_ok());
+        assert!(check_output(&existing.with_extension("missing"), false).is_ok());
+    }
+}
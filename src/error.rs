@@ -0,0 +1,311 @@
+// The LibRaw codes and mappings are unused in builds with only the rawloader backend.
+#![cfg_attr(not(feature = "libraw"), allow(dead_code))]
+
+use libc::c_int;
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// The step of the decoding pipeline that failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeStage {
+    Init,
+    Open,
+    Unpack,
+    Process,
+    Thumbnail,
+    Image,
+}
+
+impl fmt::Display for DecodeStage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            DecodeStage::Init => "init",
+            DecodeStage::Open => "open",
+            DecodeStage::Unpack => "unpack",
+            DecodeStage::Process => "process",
+            DecodeStage::Thumbnail => "thumbnail",
+            DecodeStage::Image => "image",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A LibRaw return code (`enum LibRaw_errors` in libraw_const.h).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LibRawError {
+    Unspecified,
+    FileUnsupported,
+    NonexistentImage,
+    OutOfOrderCall,
+    NoThumbnail,
+    UnsupportedThumbnail,
+    InputClosed,
+    NotImplemented,
+    NonexistentThumbnail,
+    OutOfMemory,
+    DataError,
+    IoError,
+    CancelledByCallback,
+    BadCrop,
+    TooBig,
+    MempoolOverflow,
+    /// A positive code is an `errno` value, e.g. from `libraw_open_file`.
+    Os(i32),
+    /// A code this enum does not know about (newer LibRaw).
+    Unknown(i32),
+}
+
+impl LibRawError {
+    pub fn from_code(code: c_int) -> Self {
+        match code {
+            -1 => LibRawError::Unspecified,
+            -2 => LibRawError::FileUnsupported,
+            -3 => LibRawError::NonexistentImage,
+            -4 => LibRawError::OutOfOrderCall,
+            -5 => LibRawError::NoThumbnail,
+            -6 => LibRawError::UnsupportedThumbnail,
+            -7 => LibRawError::InputClosed,
+            -8 => LibRawError::NotImplemented,
+            -9 => LibRawError::NonexistentThumbnail,
+            -100007 => LibRawError::OutOfMemory,
+            -100008 => LibRawError::DataError,
+            -100009 => LibRawError::IoError,
+            -100010 => LibRawError::CancelledByCallback,
+            -100011 => LibRawError::BadCrop,
+            -100012 => LibRawError::TooBig,
+            -100013 => LibRawError::MempoolOverflow,
+            code if code > 0 => LibRawError::Os(code),
+            code => LibRawError::Unknown(code),
+        }
+    }
+
+    pub fn code(&self) -> c_int {
+        match self {
+            LibRawError::Unspecified => -1,
+            LibRawError::FileUnsupported => -2,
+            LibRawError::NonexistentImage => -3,
+            LibRawError::OutOfOrderCall => -4,
+            LibRawError::NoThumbnail => -5,
+            LibRawError::UnsupportedThumbnail => -6,
+            LibRawError::InputClosed => -7,
+            LibRawError::NotImplemented => -8,
+            LibRawError::NonexistentThumbnail => -9,
+            LibRawError::OutOfMemory => -100007,
+            LibRawError::DataError => -100008,
+            LibRawError::IoError => -100009,
+            LibRawError::CancelledByCallback => -100010,
+            LibRawError::BadCrop => -100011,
+            LibRawError::TooBig => -100012,
+            LibRawError::MempoolOverflow => -100013,
+            LibRawError::Os(code) | LibRawError::Unknown(code) => *code,
+        }
+    }
+
+    /// Mirrors `LIBRAW_FATAL_ERROR`: after a fatal error the handle must be recycled.
+    pub fn is_fatal(&self) -> bool {
+        self.code() < -100000
+    }
+}
+
+impl fmt::Display for LibRawError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LibRawError::Unspecified => f.write_str("unspecified error"),
+            LibRawError::FileUnsupported => f.write_str("file format not supported"),
+            LibRawError::NonexistentImage => f.write_str("requested image does not exist"),
+            LibRawError::OutOfOrderCall => f.write_str("LibRaw functions called out of order"),
+            LibRawError::NoThumbnail => f.write_str("no thumbnail in file"),
+            LibRawError::UnsupportedThumbnail => f.write_str("unsupported thumbnail format"),
+            LibRawError::InputClosed => f.write_str("input stream is closed"),
+            LibRawError::NotImplemented => f.write_str("not implemented"),
+            LibRawError::NonexistentThumbnail => f.write_str("requested thumbnail does not exist"),
+            LibRawError::OutOfMemory => f.write_str("out of memory"),
+            LibRawError::DataError => f.write_str("corrupt or truncated data"),
+            LibRawError::IoError => f.write_str("input/output error"),
+            LibRawError::CancelledByCallback => f.write_str("cancelled"),
+            LibRawError::BadCrop => f.write_str("bad crop box"),
+            LibRawError::TooBig => f.write_str("image too big"),
+            LibRawError::MempoolOverflow => f.write_str("LibRaw memory pool overflow"),
+            LibRawError::Os(code) => write!(f, "{}", io::Error::from_raw_os_error(*code)),
+            LibRawError::Unknown(code) => write!(f, "unknown LibRaw error code {}", code),
+        }
+    }
+}
+
+/// Errors returned by the decoder.
+#[derive(Debug)]
+pub enum DecodeError {
+    /// A LibRaw call failed.
+    LibRaw { stage: DecodeStage, error: LibRawError },
+    /// LibRaw could not be loaded or has an incompatible version.
+    LibraryUnavailable(String),
+    /// Reading the input failed outside of LibRaw.
+    Io { stage: DecodeStage, source: io::Error },
+    /// The path cannot be handed to LibRaw (e.g. it contains a NUL byte).
+    InvalidPath(String),
+    /// The image dimensions overflow the size of a buffer.
+    DimensionsTooLarge { stage: DecodeStage, width: u32, height: u32 },
+    /// LibRaw returned a buffer whose size does not match its dimensions.
+    SizeMismatch { stage: DecodeStage, expected: usize, actual: usize },
+    /// LibRaw returned an image layout we cannot convert.
+    UnsupportedFormat { stage: DecodeStage, detail: String },
+    /// An embedded (JPEG) image could not be decoded.
+    EmbeddedImage { stage: DecodeStage, source: image::ImageError },
+    /// The decode was stopped through its `Progress` handle.
+    Cancelled,
+}
+
+impl DecodeError {
+    pub fn libraw(stage: DecodeStage, code: c_int) -> Self {
+        DecodeError::LibRaw { stage, error: LibRawError::from_code(code) }
+    }
+
+    /// The pipeline step that failed, if the error is tied to one.
+    pub fn stage(&self) -> Option<DecodeStage> {
+        match self {
+            DecodeError::LibRaw { stage, .. }
+            | DecodeError::DimensionsTooLarge { stage, .. }
+            | DecodeError::SizeMismatch { stage, .. }
+            | DecodeError::UnsupportedFormat { stage, .. }
+            | DecodeError::EmbeddedImage { stage, .. }
+            | DecodeError::Io { stage, .. } => Some(*stage),
+            DecodeError::LibraryUnavailable(_) => Some(DecodeStage::Init),
+            DecodeError::InvalidPath(_) | DecodeError::Cancelled => None,
+        }
+    }
+
+    /// The underlying LibRaw error, if any.
+    pub fn libraw_error(&self) -> Option<LibRawError> {
+        match self {
+            DecodeError::LibRaw { error, .. } => Some(*error),
+            _ => None,
+        }
+    }
+
+    /// Whether the decode was cancelled rather than failed, either by us or by LibRaw's callback.
+    pub fn is_cancelled(&self) -> bool {
+        matches!(
+            self,
+            DecodeError::Cancelled | DecodeError::LibRaw { error: LibRawError::CancelledByCallback, .. }
+        )
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::LibRaw { stage, error } => {
+                write!(f, "{} failed: {} (LibRaw error code {})", stage, error, error.code())
+            }
+            DecodeError::LibraryUnavailable(reason) => write!(f, "LibRaw is not available: {}", reason),
+            DecodeError::Io { stage, source } => write!(f, "{} failed: {}", stage, source),
+            DecodeError::InvalidPath(e) => write!(f, "invalid path: {}", e),
+            DecodeError::DimensionsTooLarge { stage, width, height } => {
+                write!(f, "{} failed: image dimensions {}x{} too large", stage, width, height)
+            }
+            DecodeError::SizeMismatch { stage, expected, actual } => write!(
+                f,
+                "{} failed: processed image data size ({}) does not match expected size ({})",
+                stage, actual, expected
+            ),
+            DecodeError::UnsupportedFormat { stage, detail } => {
+                write!(f, "{} failed: unsupported image format: {}", stage, detail)
+            }
+            DecodeError::EmbeddedImage { stage, source } => {
+                write!(f, "{} failed: could not decode embedded image: {}", stage, source)
+            }
+            DecodeError::Cancelled => f.write_str("cancelled"),
+        }
+    }
+}
+
+impl Error for DecodeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            DecodeError::EmbeddedImage { source, .. } => Some(source),
+            DecodeError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every code in `enum LibRaw_errors`, with the variant it maps to.
+    const CODES: [(c_int, LibRawError); 16] = [
+        (-1, LibRawError::Unspecified),
+        (-2, LibRawError::FileUnsupported),
+        (-3, LibRawError::NonexistentImage),
+        (-4, LibRawError::OutOfOrderCall),
+        (-5, LibRawError::NoThumbnail),
+        (-6, LibRawError::UnsupportedThumbnail),
+        (-7, LibRawError::InputClosed),
+        (-8, LibRawError::NotImplemented),
+        (-9, LibRawError::NonexistentThumbnail),
+        (-100007, LibRawError::OutOfMemory),
+        (-100008, LibRawError::DataError),
+        (-100009, LibRawError::IoError),
+        (-100010, LibRawError::CancelledByCallback),
+        (-100011, LibRawError::BadCrop),
+        (-100012, LibRawError::TooBig),
+        (-100013, LibRawError::MempoolOverflow),
+    ];
+
+    #[test]
+    fn known_codes_round_trip() {
+        for (code, error) in CODES {
+            assert_eq!(LibRawError::from_code(code), error);
+            assert_eq!(error.code(), code);
+        }
+    }
+
+    #[test]
+    fn positive_codes_are_os_errors() {
+        let error = LibRawError::from_code(libc::ENOENT);
+        assert_eq!(error, LibRawError::Os(libc::ENOENT));
+        assert_eq!(error.code(), libc::ENOENT);
+        assert_eq!(error.to_string(), io::Error::from_raw_os_error(libc::ENOENT).to_string());
+    }
+
+    #[test]
+    fn unknown_negative_codes_are_kept() {
+        assert_eq!(LibRawError::from_code(-10), LibRawError::Unknown(-10));
+        assert_eq!(LibRawError::from_code(-100014), LibRawError::Unknown(-100014));
+        assert_eq!(LibRawError::Unknown(-10).code(), -10);
+    }
+
+    #[test]
+    fn only_codes_below_minus_100000_are_fatal() {
+        for (code, error) in CODES {
+            assert_eq!(error.is_fatal(), code < -100000, "{:?}", error);
+        }
+        assert!(!LibRawError::Os(libc::ENOENT).is_fatal());
+    }
+
+    #[test]
+    fn stage_names_the_failed_step() {
+        assert_eq!(DecodeError::libraw(DecodeStage::Unpack, -100008).stage(), Some(DecodeStage::Unpack));
+        let mismatch = DecodeError::SizeMismatch { stage: DecodeStage::Image, expected: 12, actual: 6 };
+        assert_eq!(mismatch.stage(), Some(DecodeStage::Image));
+        assert_eq!(DecodeError::LibraryUnavailable("not found".into()).stage(), Some(DecodeStage::Init));
+        assert_eq!(DecodeError::InvalidPath("nul byte".into()).stage(), None);
+        assert_eq!(DecodeError::Cancelled.stage(), None);
+    }
+
+    #[test]
+    fn cancellation_is_recognized_from_either_side() {
+        assert!(DecodeError::Cancelled.is_cancelled());
+        assert!(DecodeError::libraw(DecodeStage::Process, -100010).is_cancelled());
+        assert!(!DecodeError::libraw(DecodeStage::Process, -100008).is_cancelled());
+    }
+
+    #[test]
+    fn messages_name_the_stage_and_code() {
+        let error = DecodeError::libraw(DecodeStage::Unpack, -100008);
+        assert_eq!(error.libraw_error(), Some(LibRawError::DataError));
+        assert_eq!(error.to_string(), "unpack failed: corrupt or truncated data (LibRaw error code -100008)");
+    }
+}
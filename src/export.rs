@@ -0,0 +1,472 @@
+//! Writing decoded images to disk, with per-format options, resizing and output sharpening.
+
+use crate::decoder::BitDepth;
+use image::codecs::png::{self, PngEncoder};
+use image::codecs::webp::WebPEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageFormat};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+/// The file formats images can be exported to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Png,
+    Jpeg,
+    Tiff,
+    WebP,
+    Avif,
+    /// 32-bit float OpenEXR.
+    Exr,
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 6] = [
+        ExportFormat::Png,
+        ExportFormat::Jpeg,
+        ExportFormat::Tiff,
+        ExportFormat::WebP,
+        ExportFormat::Avif,
+        ExportFormat::Exr,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ExportFormat::Png => "PNG",
+            ExportFormat::Jpeg => "JPEG",
+            ExportFormat::Tiff => "TIFF",
+            ExportFormat::WebP => "WebP",
+            ExportFormat::Avif => "AVIF",
+            ExportFormat::Exr => "OpenEXR",
+        }
+    }
+
+    /// The extensions for file dialogs; the first one is used for new files.
+    pub fn extensions(&self) -> &'static [&'static str] {
+        match self {
+            ExportFormat::Png => &["png"],
+            ExportFormat::Jpeg => &["jpg", "jpeg"],
+            ExportFormat::Tiff => &["tif", "tiff"],
+            ExportFormat::WebP => &["webp"],
+            ExportFormat::Avif => &["avif"],
+            ExportFormat::Exr => &["exr"],
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        self.extensions()[0]
+    }
+
+    /// The format whose extensions include `extension`, ignoring case.
+    pub fn from_extension(extension: &str) -> Option<ExportFormat> {
+        let extension = extension.to_ascii_lowercase();
+        Self::ALL.into_iter().find(|format| format.extensions().contains(&extension.as_str()))
+    }
+
+    /// Whether this build can write the format; AVIF needs the `avif` feature.
+    pub fn is_available(&self) -> bool {
+        *self != ExportFormat::Avif || cfg!(feature = "avif")
+    }
+
+    /// Whether `ExportSettings::quality` applies.
+    pub fn has_quality(&self) -> bool {
+        matches!(self, ExportFormat::Jpeg | ExportFormat::WebP | ExportFormat::Avif)
+    }
+}
+
+/// How JPEG stores colour relative to brightness.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChromaSubsampling {
+    /// Full colour resolution.
+    Yuv444,
+    /// Half horizontal colour resolution.
+    Yuv422,
+    /// Half horizontal and vertical colour resolution; the smallest files.
+    Yuv420,
+}
+
+impl ChromaSubsampling {
+    pub const ALL: [ChromaSubsampling; 3] =
+        [ChromaSubsampling::Yuv444, ChromaSubsampling::Yuv422, ChromaSubsampling::Yuv420];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ChromaSubsampling::Yuv444 => "4:4:4",
+            ChromaSubsampling::Yuv422 => "4:2:2",
+            ChromaSubsampling::Yuv420 => "4:2:0",
+        }
+    }
+
+    fn sampling_factor(&self) -> jpeg_encoder::SamplingFactor {
+        match self {
+            ChromaSubsampling::Yuv444 => jpeg_encoder::SamplingFactor::R_4_4_4,
+            ChromaSubsampling::Yuv422 => jpeg_encoder::SamplingFactor::R_4_2_2,
+            ChromaSubsampling::Yuv420 => jpeg_encoder::SamplingFactor::R_4_2_0,
+        }
+    }
+}
+
+/// Lossless TIFF compression schemes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TiffCompression {
+    None,
+    Lzw,
+    Deflate,
+    PackBits,
+}
+
+impl TiffCompression {
+    pub const ALL: [TiffCompression; 4] =
+        [TiffCompression::None, TiffCompression::Lzw, TiffCompression::Deflate, TiffCompression::PackBits];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            TiffCompression::None => "None",
+            TiffCompression::Lzw => "LZW",
+            TiffCompression::Deflate => "Deflate",
+            TiffCompression::PackBits => "PackBits",
+        }
+    }
+}
+
+/// The output size. Long edge and megapixels only ever shrink the image.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Resize {
+    Original,
+    LongEdge(u32),
+    Megapixels(f32),
+    Percent(f32),
+}
+
+impl Resize {
+    /// The size an image of `width` x `height` pixels is exported at.
+    pub fn target_size(&self, width: u32, height: u32) -> (u32, u32) {
+        let scale = match *self {
+            Resize::Original => 1.0,
+            Resize::LongEdge(edge) => (edge as f64 / width.max(height) as f64).min(1.0),
+            Resize::Megapixels(mp) => (mp as f64 * 1e6 / (width as f64 * height as f64)).sqrt().min(1.0),
+            Resize::Percent(percent) => percent as f64 / 100.0,
+        };
+        let scaled = |side: u32| ((side as f64 * scale).round() as u32).max(1);
+        (scaled(width), scaled(height))
+    }
+}
+
+/// Resampling filters for resizing, from fastest to sharpest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResizeFilter {
+    Bilinear,
+    Bicubic,
+    Lanczos3,
+}
+
+impl ResizeFilter {
+    pub const ALL: [ResizeFilter; 3] = [ResizeFilter::Bilinear, ResizeFilter::Bicubic, ResizeFilter::Lanczos3];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ResizeFilter::Bilinear => "Bilinear",
+            ResizeFilter::Bicubic => "Bicubic",
+            ResizeFilter::Lanczos3 => "Lanczos",
+        }
+    }
+
+    fn filter_type(&self) -> FilterType {
+        match self {
+            ResizeFilter::Bilinear => FilterType::Triangle,
+            ResizeFilter::Bicubic => FilterType::CatmullRom,
+            ResizeFilter::Lanczos3 => FilterType::Lanczos3,
+        }
+    }
+}
+
+/// Unsharp masking applied after resizing.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sharpening {
+    /// Blur radius (standard deviation) in output pixels.
+    pub radius: f32,
+    /// Minimum difference, in 8-bit levels whatever the depth of the image, for a pixel to be
+    /// sharpened; keeps noise down.
+    pub threshold: i32,
+}
+
+impl Default for Sharpening {
+    fn default() -> Self {
+        Self { radius: 0.8, threshold: 2 }
+    }
+}
+
+impl Sharpening {
+    /// Unsharp masks `image`. `unsharpen` compares samples in the levels of the buffer, so the
+    /// threshold is scaled to 16-bit levels for deeper images. Float images are sharpened as
+    /// 16-bit, because `unsharpen` would round their samples to 0 or 1.
+    fn apply(&self, image: &DynamicImage) -> DynamicImage {
+        let sixteen_bit = self.threshold.saturating_mul(257);
+        match image {
+            DynamicImage::ImageLuma8(_)
+            | DynamicImage::ImageLumaA8(_)
+            | DynamicImage::ImageRgb8(_)
+            | DynamicImage::ImageRgba8(_) => image.unsharpen(self.radius, self.threshold),
+            DynamicImage::ImageRgb32F(_) => {
+                let sharpened = DynamicImage::ImageRgb16(image.to_rgb16()).unsharpen(self.radius, sixteen_bit);
+                DynamicImage::ImageRgb32F(sharpened.to_rgb32f())
+            }
+            DynamicImage::ImageRgba32F(_) => {
+                let sharpened = DynamicImage::ImageRgba16(image.to_rgba16()).unsharpen(self.radius, sixteen_bit);
+                DynamicImage::ImageRgba32F(sharpened.to_rgba32f())
+            }
+            _ => image.unsharpen(self.radius, sixteen_bit),
+        }
+    }
+}
+
+/// Everything that determines how an image is written.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportSettings {
+    pub format: ExportFormat,
+    /// Bits per channel for PNG and TIFF; the other formats have a fixed depth.
+    pub depth: BitDepth,
+    /// 1-100, for JPEG, lossy WebP and AVIF.
+    pub quality: u8,
+    pub subsampling: ChromaSubsampling,
+    pub tiff_compression: TiffCompression,
+    /// Lossy WebP needs the `webp` feature; without it WebP is always lossless.
+    pub webp_lossless: bool,
+    /// 1 (slowest, smallest) to 10 (fastest).
+    pub avif_speed: u8,
+    pub resize: Resize,
+    pub filter: ResizeFilter,
+    pub sharpening: Option<Sharpening>,
+}
+
+impl Default for ExportSettings {
+    fn default() -> Self {
+        Self {
+            format: ExportFormat::Png,
+            depth: BitDepth::Eight,
+            quality: 90,
+            subsampling: ChromaSubsampling::Yuv420,
+            tiff_compression: TiffCompression::Lzw,
+            webp_lossless: !cfg!(feature = "webp"),
+            avif_speed: 6,
+            resize: Resize::Original,
+            filter: ResizeFilter::Lanczos3,
+            sharpening: None,
+        }
+    }
+}
+
+impl ExportSettings {
+    /// Settings for `format` that keep the full depth of the decode, as far as the format allows.
+    pub fn for_format(format: ExportFormat, depth: BitDepth) -> Self {
+        Self { format, depth, ..Self::default() }
+    }
+
+    /// The depth to decode at so that nothing the export keeps is lost.
+    pub fn decode_depth(&self) -> BitDepth {
+        match self.format {
+            ExportFormat::Png | ExportFormat::Tiff => self.depth,
+            ExportFormat::Exr => BitDepth::Sixteen,
+            _ => BitDepth::Eight,
+        }
+    }
+}
+
+/// Saves `image` in the format given by the extension of `path`, with default options.
+/// PNG and TIFF keep 16-bit data; other formats are written as 8-bit.
+pub fn save_image(image: &DynamicImage, path: &Path) -> Result<(), String> {
+    let extension = path.extension().unwrap_or_default().to_string_lossy();
+    let format = ExportFormat::from_extension(&extension)
+        .ok_or_else(|| format!("unsupported file extension: \"{}\"", extension))?;
+    let depth = match image {
+        DynamicImage::ImageRgb8(_) | DynamicImage::ImageRgba8(_) => BitDepth::Eight,
+        _ => BitDepth::Sixteen,
+    };
+    export_image(image, path, &ExportSettings::for_format(format, depth))
+}
+
+/// Resizes and sharpens `image` as `settings` say and writes it to `path`.
+pub fn export_image(image: &DynamicImage, path: &Path, settings: &ExportSettings) -> Result<(), String> {
+    if !settings.format.is_available() {
+        return Err(format!("{} export is not available in this build", settings.format.label()));
+    }
+    let (width, height) = settings.resize.target_size(image.width(), image.height());
+    let mut resized = None;
+    if (width, height) != (image.width(), image.height()) {
+        resized = Some(image.resize_exact(width, height, settings.filter.filter_type()));
+    }
+    if let Some(sharpening) = settings.sharpening {
+        let source = resized.as_ref().unwrap_or(image);
+        resized = Some(sharpening.apply(source));
+    }
+    write(resized.as_ref().unwrap_or(image), path, settings)
+}
+
+fn write(image: &DynamicImage, path: &Path, settings: &ExportSettings) -> Result<(), String> {
+    let file = || File::create(path).map(BufWriter::new).map_err(|e| e.to_string());
+    let high_depth = settings.depth == BitDepth::Sixteen;
+    match settings.format {
+        ExportFormat::Png => {
+            let encoder = PngEncoder::new_with_quality(file()?, png::CompressionType::Best, png::FilterType::Adaptive);
+            if high_depth {
+                DynamicImage::ImageRgb16(image.to_rgb16()).write_with_encoder(encoder)
+            } else {
+                DynamicImage::ImageRgb8(image.to_rgb8()).write_with_encoder(encoder)
+            }
+            .map_err(|e| e.to_string())
+        }
+        ExportFormat::Jpeg => {
+            let rgb = image.to_rgb8();
+            let (width, height) = rgb.dimensions();
+            let too_large = || format!("JPEG is limited to 65535 pixels per side, the image is {}x{}", width, height);
+            let width = u16::try_from(width).map_err(|_| too_large())?;
+            let height = u16::try_from(height).map_err(|_| too_large())?;
+            let mut encoder = jpeg_encoder::Encoder::new(file()?, settings.quality.clamp(1, 100));
+            encoder.set_sampling_factor(settings.subsampling.sampling_factor());
+            encoder.encode(rgb.as_raw(), width, height, jpeg_encoder::ColorType::Rgb).map_err(|e| e.to_string())
+        }
+        ExportFormat::Tiff => write_tiff(image, file()?, high_depth, settings.tiff_compression),
+        ExportFormat::WebP => {
+            let rgb = DynamicImage::ImageRgb8(image.to_rgb8());
+            #[cfg(feature = "webp")]
+            if !settings.webp_lossless {
+                // Lossy encoding through libwebp; deprecated in `image` but still the only option in 0.24.
+                #[allow(deprecated)]
+                let quality = image::codecs::webp::WebPQuality::lossy(settings.quality);
+                #[allow(deprecated)]
+                let encoder = WebPEncoder::new_with_quality(file()?, quality);
+                return rgb.write_with_encoder(encoder).map_err(|e| e.to_string());
+            }
+            rgb.write_with_encoder(WebPEncoder::new_lossless(file()?)).map_err(|e| e.to_string())
+        }
+        #[cfg(feature = "avif")]
+        ExportFormat::Avif => {
+            let encoder =
+                image::codecs::avif::AvifEncoder::new_with_speed_quality(file()?, settings.avif_speed, settings.quality);
+            DynamicImage::ImageRgb8(image.to_rgb8()).write_with_encoder(encoder).map_err(|e| e.to_string())
+        }
+        #[cfg(not(feature = "avif"))]
+        ExportFormat::Avif => unreachable!("checked by export_image"),
+        // The rendered values scaled to 0-1, with the gamma curve of the render;
+        // choose the linear curve in the Processing panel for scene-linear data.
+        ExportFormat::Exr => DynamicImage::ImageRgb32F(image.to_rgb32f())
+            .save_with_format(path, ImageFormat::OpenExr)
+            .map_err(|e| e.to_string()),
+    }
+}
+
+fn write_tiff(
+    image: &DynamicImage,
+    file: BufWriter<File>,
+    high_depth: bool,
+    compression: TiffCompression,
+) -> Result<(), String> {
+    use tiff::encoder::colortype::{RGB16, RGB8};
+    use tiff::encoder::compression::{Deflate, Lzw, Packbits, Uncompressed};
+    use tiff::encoder::TiffEncoder;
+
+    let mut encoder = TiffEncoder::new(file).map_err(|e| e.to_string())?;
+    let (width, height) = (image.width(), image.height());
+    let result = if high_depth {
+        let rgb = image.to_rgb16();
+        match compression {
+            TiffCompression::None => encoder.write_image_with_compression::<RGB16, _>(width, height, Uncompressed, &rgb),
+            TiffCompression::Lzw => encoder.write_image_with_compression::<RGB16, _>(width, height, Lzw, &rgb),
+            TiffCompression::Deflate => {
+                encoder.write_image_with_compression::<RGB16, _>(width, height, Deflate::default(), &rgb)
+            }
+            TiffCompression::PackBits => encoder.write_image_with_compression::<RGB16, _>(width, height, Packbits, &rgb),
+        }
+    } else {
+        let rgb = image.to_rgb8();
+        match compression {
+            TiffCompression::None => encoder.write_image_with_compression::<RGB8, _>(width, height, Uncompressed, &rgb),
+            TiffCompression::Lzw => encoder.write_image_with_compression::<RGB8, _>(width, height, Lzw, &rgb),
+            TiffCompression::Deflate => {
+                encoder.write_image_with_compression::<RGB8, _>(width, height, Deflate::default(), &rgb)
+            }
+            TiffCompression::PackBits => encoder.write_image_with_compression::<RGB8, _>(width, height, Packbits, &rgb),
+        }
+    };
+    result.map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, Rgb32FImage, RgbImage};
+
+    /// A gray image with a vertical edge `step` 8-bit levels high in the middle.
+    fn edge(step: u8) -> RgbImage {
+        RgbImage::from_fn(16, 8, |x, _| Rgb([if x < 8 { 100 } else { 100 + step }; 3]))
+    }
+
+    /// The pixels that `sharpening` changes in `image`.
+    fn changed(image: &DynamicImage, sharpening: Sharpening) -> Vec<bool> {
+        let before = image.to_rgb16();
+        let after = sharpening.apply(image).to_rgb16();
+        before.pixels().zip(after.pixels()).map(|(a, b)| a != b).collect()
+    }
+
+    #[test]
+    fn sharpening_threshold_means_the_same_at_every_depth() {
+        for (step, threshold, sharpens) in [(40, 5, true), (40, 15, false), (6, 5, false)] {
+            let sharpening = Sharpening { radius: 1.0, threshold };
+            let eight = DynamicImage::ImageRgb8(edge(step));
+            let sixteen = DynamicImage::ImageRgb16(eight.to_rgb16());
+            let float = DynamicImage::ImageRgb32F(eight.to_rgb32f());
+            let expected = changed(&eight, sharpening);
+            assert_eq!(expected.contains(&true), sharpens, "step {} threshold {}", step, threshold);
+            assert_eq!(changed(&sixteen, sharpening), expected, "step {} threshold {}", step, threshold);
+            assert_eq!(changed(&float, sharpening), expected, "step {} threshold {}", step, threshold);
+        }
+    }
+
+    #[test]
+    fn edges_above_the_threshold_are_sharpened_alike_at_every_depth() {
+        let sharpening = Sharpening { radius: 1.0, threshold: 5 };
+        let eight = DynamicImage::ImageRgb8(edge(40));
+        assert!(changed(&eight, sharpening).contains(&true));
+        let sharpened8 = sharpening.apply(&eight).to_rgb8();
+        let sharpened16 = sharpening.apply(&DynamicImage::ImageRgb16(eight.to_rgb16())).to_rgb8();
+        for (a, b) in sharpened8.pixels().zip(sharpened16.pixels()) {
+            for (a, b) in a.0.iter().zip(b.0) {
+                assert!(a.abs_diff(b) <= 1, "{} vs {}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn float_images_stay_float_and_in_range() {
+        let image = DynamicImage::ImageRgb32F(Rgb32FImage::from_fn(16, 8, |x, _| Rgb([if x < 8 { 0.2 } else { 0.8 }; 3])));
+        let sharpened = Sharpening::default().apply(&image);
+        let DynamicImage::ImageRgb32F(sharpened) = sharpened else {
+            panic!("expected a float image");
+        };
+        assert!(sharpened.pixels().flat_map(|pixel| pixel.0).all(|sample| (0.0..=1.0).contains(&sample)));
+        assert!(sharpened.get_pixel(7, 0).0[0] < 0.2 && sharpened.get_pixel(8, 0).0[0] > 0.8);
+    }
+
+    #[test]
+    fn long_edge_keeps_the_aspect_ratio() {
+        assert_eq!(Resize::LongEdge(1500).target_size(6000, 4000), (1500, 1000));
+        assert_eq!(Resize::LongEdge(1500).target_size(4000, 6000), (1000, 1500));
+        assert_eq!(Resize::LongEdge(1000).target_size(3000, 2000), (1000, 667));
+    }
+
+    #[test]
+    fn long_edge_and_megapixels_never_upscale() {
+        assert_eq!(Resize::LongEdge(8000).target_size(6000, 4000), (6000, 4000));
+        assert_eq!(Resize::Megapixels(100.0).target_size(6000, 4000), (6000, 4000));
+        assert_eq!(Resize::Megapixels(6.0).target_size(6000, 4000), (3000, 2000));
+        assert_eq!(Resize::Original.target_size(6000, 4000), (6000, 4000));
+    }
+
+    #[test]
+    fn percent_scales_either_way_but_keeps_a_pixel() {
+        assert_eq!(Resize::Percent(50.0).target_size(6000, 4000), (3000, 2000));
+        assert_eq!(Resize::Percent(200.0).target_size(300, 200), (600, 400));
+        assert_eq!(Resize::Percent(1.0).target_size(50, 10), (1, 1));
+    }
+}
@@ -0,0 +1,236 @@
+//! The export window, and the export settings controls it shares with batch conversion.
+
+use crate::decoder::BitDepth;
+use crate::export::{
+    export_image, ChromaSubsampling, ExportFormat, ExportSettings, Resize, ResizeFilter, Sharpening, TiffCompression,
+};
+use eframe::egui;
+use image::DynamicImage;
+use rfd::FileDialog;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::Duration;
+
+/// Settings for exporting the shown image, and the export running in the background.
+#[derive(Default)]
+pub struct ExportWindow {
+    pub open: bool,
+    settings: ExportSettings,
+    /// Encoding large images (AVIF in particular) takes a while, so it runs on a worker thread.
+    pending: Option<(PathBuf, Receiver<Result<(), String>>)>,
+    /// The outcome of the last export.
+    status: Option<Result<String, String>>,
+}
+
+impl ExportWindow {
+    /// Draws the window while it is open. `image` is what gets exported and `source`
+    /// the raw file it came from, which names the output file.
+    pub fn show(&mut self, ctx: &egui::Context, image: Option<&DynamicImage>, source: Option<&Path>) {
+        self.poll(ctx);
+        let mut open = self.open;
+        egui::Window::new("Export").open(&mut open).default_width(360.0).show(ctx, |ui| {
+            ui.add_enabled_ui(self.pending.is_none(), |ui| settings_ui(ui, &mut self.settings));
+            if let Some(image) = image {
+                let (width, height) = self.settings.resize.target_size(image.width(), image.height());
+                ui.label(format!("Output: {} x {} pixels", width, height));
+            }
+            ui.separator();
+            ui.horizontal(|ui| {
+                let enabled = image.is_some() && self.pending.is_none();
+                if ui.add_enabled(enabled, egui::Button::new("Save…")).clicked() {
+                    if let Some(image) = image {
+                        self.save(ctx, image, source);
+                    }
+                }
+                if self.pending.is_some() {
+                    ui.add(egui::Spinner::new());
+                }
+            });
+            match &self.status {
+                Some(Ok(message)) => {
+                    ui.label(message);
+                }
+                Some(Err(message)) => {
+                    ui.colored_label(ui.visuals().error_fg_color, message);
+                }
+                None => {}
+            }
+        });
+        self.open = open;
+    }
+
+    fn save(&mut self, ctx: &egui::Context, image: &DynamicImage, source: Option<&Path>) {
+        let format = self.settings.format;
+        let mut dialog = FileDialog::new().add_filter(format.label(), format.extensions());
+        if let Some(stem) = source.and_then(Path::file_stem) {
+            dialog = dialog.set_file_name(format!("{}.{}", stem.to_string_lossy(), format.extension()));
+        }
+        let Some(path) = dialog.save_file() else {
+            return;
+        };
+        let (sender, receiver) = mpsc::channel();
+        let (image, worker_path, settings, ctx) = (image.clone(), path.clone(), self.settings.clone(), ctx.clone());
+        thread::spawn(move || {
+            let _ = sender.send(export_image(&image, &worker_path, &settings));
+            ctx.request_repaint();
+        });
+        self.pending = Some((path, receiver));
+        self.status = None;
+    }
+
+    fn poll(&mut self, ctx: &egui::Context) {
+        let Some((path, receiver)) = &self.pending else {
+            return;
+        };
+        let status = match receiver.try_recv() {
+            Ok(Ok(())) => Ok(format!("Saved {}", path.display())),
+            Ok(Err(e)) => Err(format!("Error saving {}: {}", path.display(), e)),
+            Err(TryRecvError::Empty) => {
+                ctx.request_repaint_after(Duration::from_millis(100));
+                return;
+            }
+            Err(TryRecvError::Disconnected) => Err(format!("Error saving {}: the export thread crashed", path.display())),
+        };
+        self.status = Some(status);
+        self.pending = None;
+    }
+}
+
+/// Draws the controls for `settings`: format and its options, resizing and sharpening.
+pub fn settings_ui(ui: &mut egui::Ui, settings: &mut ExportSettings) {
+    egui::Grid::new(ui.id().with("export_settings")).num_columns(2).show(ui, |ui| {
+        ui.label("Format");
+        egui::ComboBox::from_id_source(ui.id().with("export_format"))
+            .selected_text(settings.format.label())
+            .show_ui(ui, |ui| {
+                for format in ExportFormat::ALL {
+                    let label = egui::SelectableLabel::new(settings.format == format, format.label());
+                    let response = ui.add_enabled(format.is_available(), label);
+                    if response.clicked() {
+                        settings.format = format;
+                    }
+                    response.on_disabled_hover_text("Not available in this build (see the `avif` feature)");
+                }
+            });
+        ui.end_row();
+
+        match settings.format {
+            ExportFormat::Png | ExportFormat::Tiff => {
+                ui.label("Depth");
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut settings.depth, BitDepth::Eight, "8-bit");
+                    ui.radio_value(&mut settings.depth, BitDepth::Sixteen, "16-bit");
+                });
+                ui.end_row();
+            }
+            ExportFormat::Exr => {
+                ui.label("Depth");
+                ui.label("32-bit float");
+                ui.end_row();
+            }
+            _ => {}
+        }
+        match settings.format {
+            ExportFormat::Jpeg => {
+                ui.label("Chroma subsampling");
+                egui::ComboBox::from_id_source(ui.id().with("export_subsampling"))
+                    .selected_text(settings.subsampling.label())
+                    .show_ui(ui, |ui| {
+                        for subsampling in ChromaSubsampling::ALL {
+                            ui.selectable_value(&mut settings.subsampling, subsampling, subsampling.label());
+                        }
+                    });
+                ui.end_row();
+            }
+            ExportFormat::Tiff => {
+                ui.label("Compression");
+                egui::ComboBox::from_id_source(ui.id().with("export_tiff_compression"))
+                    .selected_text(settings.tiff_compression.label())
+                    .show_ui(ui, |ui| {
+                        for compression in TiffCompression::ALL {
+                            ui.selectable_value(&mut settings.tiff_compression, compression, compression.label());
+                        }
+                    });
+                ui.end_row();
+            }
+            ExportFormat::WebP => {
+                ui.label("Lossless");
+                ui.add_enabled(cfg!(feature = "webp"), egui::Checkbox::new(&mut settings.webp_lossless, ""))
+                    .on_disabled_hover_text("Lossy WebP needs the `webp` feature");
+                ui.end_row();
+            }
+            ExportFormat::Avif => {
+                ui.label("Speed");
+                ui.add(egui::Slider::new(&mut settings.avif_speed, 1..=10).text("slow to fast"));
+                ui.end_row();
+            }
+            _ => {}
+        }
+        let lossless_webp = settings.format == ExportFormat::WebP && settings.webp_lossless;
+        if settings.format.has_quality() && !lossless_webp {
+            ui.label("Quality");
+            ui.add(egui::Slider::new(&mut settings.quality, 1..=100));
+            ui.end_row();
+        }
+
+        ui.label("Resize");
+        ui.horizontal(|ui| {
+            let label = |resize: &Resize| match resize {
+                Resize::Original => "Original size",
+                Resize::LongEdge(_) => "Long edge",
+                Resize::Megapixels(_) => "Megapixels",
+                Resize::Percent(_) => "Percent",
+            };
+            egui::ComboBox::from_id_source(ui.id().with("export_resize"))
+                .selected_text(label(&settings.resize))
+                .show_ui(ui, |ui| {
+                    for resize in [Resize::Original, Resize::LongEdge(2048), Resize::Megapixels(12.0), Resize::Percent(50.0)]
+                    {
+                        let selected = std::mem::discriminant(&settings.resize) == std::mem::discriminant(&resize);
+                        if ui.selectable_label(selected, label(&resize)).clicked() && !selected {
+                            settings.resize = resize;
+                        }
+                    }
+                });
+            match &mut settings.resize {
+                Resize::Original => {}
+                Resize::LongEdge(edge) => {
+                    ui.add(egui::DragValue::new(edge).clamp_range(16..=65535).suffix(" px"));
+                }
+                Resize::Megapixels(megapixels) => {
+                    ui.add(egui::DragValue::new(megapixels).clamp_range(0.1..=200.0).speed(0.1).suffix(" MP"));
+                }
+                Resize::Percent(percent) => {
+                    ui.add(egui::DragValue::new(percent).clamp_range(1.0..=100.0).suffix(" %"));
+                }
+            }
+        });
+        ui.end_row();
+        if settings.resize != Resize::Original {
+            ui.label("Filter");
+            ui.horizontal(|ui| {
+                for filter in ResizeFilter::ALL {
+                    ui.selectable_value(&mut settings.filter, filter, filter.label());
+                }
+            });
+            ui.end_row();
+        }
+
+        ui.label("Sharpen");
+        ui.horizontal(|ui| {
+            let mut enabled = settings.sharpening.is_some();
+            ui.checkbox(&mut enabled, "");
+            match (enabled, &mut settings.sharpening) {
+                (true, None) => settings.sharpening = Some(Sharpening::default()),
+                (false, Some(_)) => settings.sharpening = None,
+                _ => {}
+            }
+            if let Some(sharpening) = &mut settings.sharpening {
+                ui.add(egui::Slider::new(&mut sharpening.radius, 0.3..=3.0).text("radius"));
+                ui.add(egui::Slider::new(&mut sharpening.threshold, 0..=20).text("threshold"));
+            }
+        });
+        ui.end_row();
+    });
+}
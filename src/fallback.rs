@@ -0,0 +1,83 @@
+//! Pure-Rust preview extraction used when LibRaw is not available.
+//!
+//! Most raw formats embed one or more JPEG previews. Without LibRaw we cannot parse the
+//! container, so we scan for JPEG start-of-image markers and keep the largest decodable one.
+
+use image::io::Reader as ImageReader;
+use image::{DynamicImage, ImageFormat};
+use std::io::Cursor;
+
+/// JPEG SOI marker followed by the first byte of the next marker.
+const JPEG_SOI: [u8; 3] = [0xFF, 0xD8, 0xFF];
+
+/// Returns the largest embedded JPEG in `data`, if any.
+pub fn extract_embedded_jpeg(data: &[u8]) -> Option<DynamicImage> {
+    let mut best: Option<(u64, usize)> = None;
+    for offset in jpeg_candidates(data) {
+        // Reading just the header is cheap; only the winner is fully decoded.
+        let reader = ImageReader::with_format(Cursor::new(&data[offset..]), ImageFormat::Jpeg);
+        if let Ok((width, height)) = reader.into_dimensions() {
+            let pixels = width as u64 * height as u64;
+            if best.is_none_or(|(best_pixels, _)| pixels > best_pixels) {
+                best = Some((pixels, offset));
+            }
+        }
+    }
+    let (_, offset) = best?;
+    image::load_from_memory_with_format(&data[offset..], ImageFormat::Jpeg).ok()
+}
+
+/// Offsets of every JPEG SOI marker in `data`.
+fn jpeg_candidates(data: &[u8]) -> impl Iterator<Item = usize> + '_ {
+    data.windows(JPEG_SOI.len())
+        .enumerate()
+        .filter(|(_, window)| *window == JPEG_SOI)
+        .map(|(offset, _)| offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jpeg(width: u32, height: u32) -> Vec<u8> {
+        let mut data = Vec::new();
+        DynamicImage::new_rgb8(width, height).write_to(&mut Cursor::new(&mut data), ImageFormat::Jpeg).unwrap();
+        data
+    }
+
+    /// A stand-in for a raw file: a TIFF header, then the previews between runs of other bytes.
+    fn container(previews: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = b"II*\0\x08\0\0\0".to_vec();
+        for preview in previews {
+            data.extend_from_slice(&[0x55; 100]);
+            data.extend_from_slice(preview);
+        }
+        data.extend_from_slice(&[0xAA; 100]);
+        data
+    }
+
+    #[test]
+    fn keeps_the_largest_preview() {
+        let small = jpeg(16, 8);
+        let large = jpeg(64, 32);
+        for previews in [vec![small.clone(), large.clone()], vec![large, small]] {
+            let preview = extract_embedded_jpeg(&container(&previews)).unwrap();
+            assert_eq!((preview.width(), preview.height()), (64, 32));
+        }
+    }
+
+    #[test]
+    fn ignores_markers_that_do_not_start_a_jpeg() {
+        let mut data = container(&[jpeg(16, 8)]);
+        data.extend_from_slice(&[0xFF, 0xD8, 0xFF, 0x00, 0x01, 0x02]);
+        let preview = extract_embedded_jpeg(&data).unwrap();
+        assert_eq!((preview.width(), preview.height()), (16, 8));
+    }
+
+    #[test]
+    fn files_without_a_jpeg_have_no_preview() {
+        assert!(extract_embedded_jpeg(&container(&[])).is_none());
+        assert!(extract_embedded_jpeg(&[0xFF, 0xD8, 0xFF]).is_none());
+        assert!(extract_embedded_jpeg(&[]).is_none());
+    }
+}
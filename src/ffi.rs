@@ -0,0 +1,261 @@
+//! Raw LibRaw bindings.
+//!
+//! The decoder goes through `LibRawApi`, a table of the LibRaw entry points, so the same
+//! code works whether LibRaw is linked at build time (default) or loaded at runtime
+//! (`dynamic` feature). The `lrv_*` accessors from libraw_shim.c are always linked.
+
+use libc::{c_char, c_int, c_uint, c_void, size_t};
+use std::ffi::CStr;
+use std::sync::OnceLock;
+
+/// The oldest LibRaw release whose API we use (`libraw_unpack_thumb` semantics, `user_flip`).
+const MIN_VERSION: c_int = libraw_version_number(0, 20, 0);
+
+const fn libraw_version_number(major: c_int, minor: c_int, patch: c_int) -> c_int {
+    (major << 16) | (minor << 8) | patch
+}
+
+#[repr(C)]
+pub struct LibRawData {
+    _private: [u8; 0],
+}
+
+#[repr(C)]
+pub struct LibRawProcessedImage {
+    pub type_: c_int,
+    pub colors: c_int,
+    pub height: c_int,
+    pub width: c_int,
+    pub bits: c_int,
+    pub data: *mut c_void,
+    pub data_size: c_int,
+    // Other fields are omitted.
+}
+
+/// Mirror of `lrv_metadata` in libraw_shim.c.
+#[repr(C)]
+pub struct LrvMetadata {
+    pub make: [c_char; 64],
+    pub model: [c_char; 64],
+    pub lens: [c_char; 128],
+    pub serial: [c_char; 64],
+    pub artist: [c_char; 64],
+    pub iso_speed: f32,
+    pub shutter: f32,
+    pub aperture: f32,
+    pub focal_len: f32,
+    pub timestamp: i64,
+    pub latitude: [f32; 3],
+    pub longitude: [f32; 3],
+    pub altitude: f32,
+    pub latref: c_char,
+    pub longref: c_char,
+    pub altref: c_char,
+    pub gpsparsed: c_char,
+    pub flash_used: f32,
+    pub flip: c_int,
+}
+
+/// Mirror of `lrv_params` in libraw_shim.c.
+#[repr(C)]
+pub struct LrvParams {
+    pub use_camera_wb: c_int,
+    pub use_auto_wb: c_int,
+    pub user_mul: [f32; 4],
+    pub user_qual: c_int,
+    pub exp_correc: c_int,
+    pub exp_shift: f32,
+    pub exp_preser: f32,
+    pub highlight: c_int,
+    pub threshold: f32,
+    pub bright: f32,
+    pub no_auto_bright: c_int,
+    pub gamm: [f64; 2],
+    pub output_color: c_int,
+}
+
+// Accessors for libraw_data_t fields, see libraw_shim.c.
+unsafe extern "C" {
+    pub fn lrv_set_half_size(raw: *mut LibRawData, half_size: c_int);
+    pub fn lrv_get_metadata(raw: *const LibRawData, out: *mut LrvMetadata);
+    pub fn lrv_set_params(raw: *mut LibRawData, params: *const LrvParams);
+    pub fn lrv_set_user_flip(raw: *mut LibRawData, flip: c_int);
+    /// `LIBRAW_VERSION` of the headers the shim was compiled against.
+    fn lrv_header_version() -> c_int;
+}
+
+/// `progress_callback` in libraw_types.h. Returning non-zero cancels the running call.
+pub type ProgressCallback =
+    unsafe extern "C" fn(data: *mut c_void, stage: c_int, iteration: c_int, expected: c_int) -> c_int;
+
+/// The LibRaw entry points used by the decoder.
+pub struct LibRawApi {
+    pub init: unsafe extern "C" fn(flags: c_uint) -> *mut LibRawData,
+    pub open_file: unsafe extern "C" fn(raw: *mut LibRawData, filename: *const c_char) -> c_int,
+    /// Wide-character paths; LibRaw only exports this from MSVC builds.
+    #[cfg(all(windows, target_env = "msvc"))]
+    pub open_wfile: unsafe extern "C" fn(raw: *mut LibRawData, filename: *const u16) -> c_int,
+    /// LibRaw reads from the buffer until the handle is closed or reopened.
+    pub open_buffer: unsafe extern "C" fn(raw: *mut LibRawData, buffer: *const c_void, size: size_t) -> c_int,
+    pub unpack: unsafe extern "C" fn(raw: *mut LibRawData) -> c_int,
+    pub unpack_thumb: unsafe extern "C" fn(raw: *mut LibRawData) -> c_int,
+    pub set_output_bps: unsafe extern "C" fn(raw: *mut LibRawData, bps: c_int),
+    pub dcraw_process: unsafe extern "C" fn(raw: *mut LibRawData) -> c_int,
+    pub dcraw_make_mem_thumb:
+        unsafe extern "C" fn(raw: *mut LibRawData, err: *mut c_int) -> *mut LibRawProcessedImage,
+    pub dcraw_make_mem_image:
+        unsafe extern "C" fn(raw: *mut LibRawData, err: *mut c_int) -> *mut LibRawProcessedImage,
+    pub dcraw_clear_mem: unsafe extern "C" fn(image: *mut LibRawProcessedImage),
+    pub set_progress_handler: unsafe extern "C" fn(raw: *mut LibRawData, callback: ProgressCallback, data: *mut c_void),
+    pub strprogress: unsafe extern "C" fn(stage: c_int) -> *const c_char,
+    pub close: unsafe extern "C" fn(raw: *mut LibRawData),
+    pub version: unsafe extern "C" fn() -> *const c_char,
+    pub version_number: unsafe extern "C" fn() -> c_int,
+    /// Keeps the runtime-loaded library mapped for as long as the function pointers live.
+    #[cfg(feature = "dynamic")]
+    _library: libloading::Library,
+}
+
+impl LibRawApi {
+    /// The version string of the LibRaw library in use, e.g. "0.21.3-Release".
+    pub fn version_string(&self) -> String {
+        unsafe { CStr::from_ptr((self.version)()) }.to_string_lossy().into_owned()
+    }
+
+    /// Rejects libraries that are too old, or whose structs may not match the shim's headers.
+    fn check_version(&self) -> Result<(), String> {
+        let runtime = unsafe { (self.version_number)() };
+        let header = unsafe { lrv_header_version() };
+        if runtime < MIN_VERSION {
+            return Err(format!(
+                "LibRaw {} is too old; at least {}.{} is required",
+                self.version_string(),
+                MIN_VERSION >> 16,
+                (MIN_VERSION >> 8) & 0xff
+            ));
+        }
+        // libraw_data_t changes between minor releases, and the shim reads its fields directly.
+        if runtime >> 8 != header >> 8 {
+            return Err(format!(
+                "LibRaw {} does not match the headers this program was built with ({}.{})",
+                self.version_string(),
+                header >> 16,
+                (header >> 8) & 0xff
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The LibRaw API, or a description of why it is unavailable.
+/// The library is loaded and version-checked on first use.
+pub fn api() -> Result<&'static LibRawApi, String> {
+    static API: OnceLock<Result<LibRawApi, String>> = OnceLock::new();
+    API.get_or_init(|| {
+        let api = load()?;
+        api.check_version()?;
+        Ok(api)
+    })
+    .as_ref()
+    .map_err(Clone::clone)
+}
+
+#[cfg(not(feature = "dynamic"))]
+fn load() -> Result<LibRawApi, String> {
+    unsafe extern "C" {
+        fn libraw_init(flags: c_uint) -> *mut LibRawData;
+        fn libraw_open_file(raw: *mut LibRawData, filename: *const c_char) -> c_int;
+        #[cfg(all(windows, target_env = "msvc"))]
+        fn libraw_open_wfile(raw: *mut LibRawData, filename: *const u16) -> c_int;
+        fn libraw_open_buffer(raw: *mut LibRawData, buffer: *const c_void, size: size_t) -> c_int;
+        fn libraw_unpack(raw: *mut LibRawData) -> c_int;
+        fn libraw_unpack_thumb(raw: *mut LibRawData) -> c_int;
+        fn libraw_set_output_bps(raw: *mut LibRawData, bps: c_int);
+        fn libraw_dcraw_process(raw: *mut LibRawData) -> c_int;
+        fn libraw_dcraw_make_mem_thumb(raw: *mut LibRawData, err: *mut c_int) -> *mut LibRawProcessedImage;
+        fn libraw_dcraw_make_mem_image(raw: *mut LibRawData, err: *mut c_int) -> *mut LibRawProcessedImage;
+        fn libraw_dcraw_clear_mem(image: *mut LibRawProcessedImage);
+        fn libraw_set_progress_handler(raw: *mut LibRawData, callback: ProgressCallback, data: *mut c_void);
+        fn libraw_strprogress(stage: c_int) -> *const c_char;
+        fn libraw_close(raw: *mut LibRawData);
+        fn libraw_version() -> *const c_char;
+        fn libraw_versionNumber() -> c_int;
+    }
+    Ok(LibRawApi {
+        init: libraw_init,
+        open_file: libraw_open_file,
+        #[cfg(all(windows, target_env = "msvc"))]
+        open_wfile: libraw_open_wfile,
+        open_buffer: libraw_open_buffer,
+        unpack: libraw_unpack,
+        unpack_thumb: libraw_unpack_thumb,
+        set_output_bps: libraw_set_output_bps,
+        dcraw_process: libraw_dcraw_process,
+        dcraw_make_mem_thumb: libraw_dcraw_make_mem_thumb,
+        dcraw_make_mem_image: libraw_dcraw_make_mem_image,
+        dcraw_clear_mem: libraw_dcraw_clear_mem,
+        set_progress_handler: libraw_set_progress_handler,
+        strprogress: libraw_strprogress,
+        close: libraw_close,
+        version: libraw_version,
+        version_number: libraw_versionNumber,
+    })
+}
+
+/// Library file names tried in order; LIBRAW_PATH overrides them.
+#[cfg(feature = "dynamic")]
+const LIBRARY_NAMES: &[&str] = if cfg!(windows) {
+    &["libraw.dll"]
+} else if cfg!(target_os = "macos") {
+    &["libraw_r.dylib", "libraw.dylib", "libraw_r.23.dylib", "libraw.23.dylib"]
+} else {
+    &["libraw_r.so.23", "libraw.so.23", "libraw_r.so.20", "libraw.so.20", "libraw_r.so", "libraw.so"]
+};
+
+#[cfg(feature = "dynamic")]
+fn load() -> Result<LibRawApi, String> {
+    let names: Vec<std::ffi::OsString> = match std::env::var_os("LIBRAW_PATH") {
+        Some(path) => vec![path],
+        None => LIBRARY_NAMES.iter().map(Into::into).collect(),
+    };
+    let mut errors = Vec::new();
+    for name in &names {
+        match unsafe { libloading::Library::new(name) }.and_then(|library| unsafe { resolve(library) }) {
+            Ok(api) => return Ok(api),
+            Err(e) => errors.push(format!("{}: {}", name.to_string_lossy(), e)),
+        }
+    }
+    Err(format!("LibRaw could not be loaded ({})", errors.join("; ")))
+}
+
+/// Looks up every entry point in `library`.
+#[cfg(feature = "dynamic")]
+unsafe fn resolve(library: libloading::Library) -> Result<LibRawApi, libloading::Error> {
+    macro_rules! symbol {
+        ($name:literal) => {
+            // SAFETY: every field of `LibRawApi` has the signature of the function of the
+            // same name in libraw.h, which is the type the symbol is read as here.
+            unsafe { *library.get(concat!($name, "\0").as_bytes())? }
+        };
+    }
+    Ok(LibRawApi {
+        init: symbol!("libraw_init"),
+        open_file: symbol!("libraw_open_file"),
+        #[cfg(all(windows, target_env = "msvc"))]
+        open_wfile: symbol!("libraw_open_wfile"),
+        open_buffer: symbol!("libraw_open_buffer"),
+        unpack: symbol!("libraw_unpack"),
+        unpack_thumb: symbol!("libraw_unpack_thumb"),
+        set_output_bps: symbol!("libraw_set_output_bps"),
+        dcraw_process: symbol!("libraw_dcraw_process"),
+        dcraw_make_mem_thumb: symbol!("libraw_dcraw_make_mem_thumb"),
+        dcraw_make_mem_image: symbol!("libraw_dcraw_make_mem_image"),
+        dcraw_clear_mem: symbol!("libraw_dcraw_clear_mem"),
+        set_progress_handler: symbol!("libraw_set_progress_handler"),
+        strprogress: symbol!("libraw_strprogress"),
+        close: symbol!("libraw_close"),
+        version: symbol!("libraw_version"),
+        version_number: symbol!("libraw_versionNumber"),
+        _library: library,
+    })
+}
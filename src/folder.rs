@@ -0,0 +1,323 @@
+//! The raw files of a folder, for stepping through a shoot.
+
+use crate::decoder::RawDecoder;
+use crate::formats::is_raw_file;
+use crate::source::RawSource;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::thread;
+use std::time::SystemTime;
+
+/// The order files are listed in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortOrder {
+    Name,
+    /// By the capture time in the metadata; files without one come last, by name.
+    CaptureTime,
+}
+
+impl SortOrder {
+    pub const ALL: [SortOrder; 2] = [SortOrder::Name, SortOrder::CaptureTime];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            SortOrder::Name => "Name",
+            SortOrder::CaptureTime => "Capture time",
+        }
+    }
+}
+
+/// The raw files next to the opened one, and which of them is shown.
+pub struct Folder {
+    files: Vec<PathBuf>,
+    index: usize,
+    /// Capture-time sorting reads every file's metadata, so it runs on a worker thread;
+    /// until it is done the files stay in name order.
+    pending: Option<Receiver<Vec<PathBuf>>>,
+}
+
+impl Folder {
+    /// Lists the raw files in the folder containing `path` and selects `path`.
+    /// `path` itself is always included, so files opened through "All files" can be browsed from too.
+    pub fn open(path: &Path, order: SortOrder, decoder: &Arc<dyn RawDecoder>) -> io::Result<Self> {
+        let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        let mut files = list_raw_files(dir)?;
+        let current = dir.join(path.file_name().unwrap_or_default());
+        if !files.contains(&current) {
+            files.push(current.clone());
+        }
+        files.sort();
+        let index = files.iter().position(|file| *file == current).unwrap_or(0);
+        let mut folder = Self { files, index, pending: None };
+        folder.sort(order, decoder);
+        Ok(folder)
+    }
+
+    /// Lists the raw files in `dir` and selects the first one.
+    pub fn open_dir(dir: &Path, order: SortOrder, decoder: &Arc<dyn RawDecoder>) -> io::Result<Self> {
+        let mut files = list_raw_files(dir)?;
+        files.sort();
+        let mut folder = Self { files, index: 0, pending: None };
+        folder.sort(order, decoder);
+        Ok(folder)
+    }
+
+    /// Re-sorts the files, keeping the current one selected.
+    pub fn sort(&mut self, order: SortOrder, decoder: &Arc<dyn RawDecoder>) {
+        self.pending = None;
+        match order {
+            SortOrder::Name => {
+                let mut files = self.files.clone();
+                files.sort();
+                self.reorder(files);
+            }
+            SortOrder::CaptureTime => {
+                let (sender, receiver) = mpsc::channel();
+                let files = self.files.clone();
+                let decoder = decoder.clone();
+                thread::spawn(move || {
+                    let _ = sender.send(sort_by_capture_time(files, decoder.as_ref()));
+                });
+                self.pending = Some(receiver);
+            }
+        }
+    }
+
+    /// Applies a finished capture-time sort. Returns true if the order changed.
+    pub fn poll(&mut self) -> bool {
+        let Some(receiver) = &self.pending else {
+            return false;
+        };
+        match receiver.try_recv() {
+            Ok(files) => {
+                self.pending = None;
+                self.reorder(files);
+                true
+            }
+            Err(mpsc::TryRecvError::Empty) => false,
+            Err(mpsc::TryRecvError::Disconnected) => {
+                self.pending = None;
+                false
+            }
+        }
+    }
+
+    /// Whether a capture-time sort is still running.
+    pub fn is_sorting(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    fn reorder(&mut self, files: Vec<PathBuf>) {
+        let current = self.files.get(self.index).cloned();
+        self.index = current.and_then(|current| files.iter().position(|file| *file == current)).unwrap_or(0);
+        self.files = files;
+    }
+
+    pub fn current(&self) -> Option<&Path> {
+        self.files.get(self.index).map(PathBuf::as_path)
+    }
+
+    pub fn files(&self) -> &[PathBuf] {
+        &self.files
+    }
+
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Makes the file at `index` the current one, if it exists.
+    pub fn select(&mut self, index: usize) -> Option<&Path> {
+        if index < self.files.len() {
+            self.index = index;
+        }
+        self.files.get(index).map(PathBuf::as_path)
+    }
+
+    /// Moves `delta` files forward (or back), stopping at either end.
+    /// Returns the new current file, or `None` if the position did not change.
+    pub fn step(&mut self, delta: isize) -> Option<&Path> {
+        let target = self.index.saturating_add_signed(delta).min(self.files.len().saturating_sub(1));
+        if target == self.index {
+            return None;
+        }
+        self.index = target;
+        self.current()
+    }
+
+    /// The files right before and after the current one, worth decoding ahead of time.
+    pub fn neighbours(&self) -> Vec<&Path> {
+        let after = self.files.get(self.index + 1);
+        let before = self.index.checked_sub(1).and_then(|index| self.files.get(index));
+        // The next file first: most culling goes forward.
+        after.into_iter().chain(before).map(PathBuf::as_path).collect()
+    }
+
+    /// One-based position and total, as shown in the toolbar ("12 / 348").
+    pub fn position(&self) -> (usize, usize) {
+        ((self.index + 1).min(self.files.len()), self.files.len())
+    }
+}
+
+/// The raw files in `dir`, in directory order: those with a raw extension, and those
+/// whose content looks like a raw file (see `is_raw_file`).
+pub fn list_raw_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let file = entry?.path();
+        if file.is_file() && is_raw_file(&file) {
+            files.push(file);
+        }
+    }
+    Ok(files)
+}
+
+fn sort_by_capture_time(files: Vec<PathBuf>, decoder: &dyn RawDecoder) -> Vec<PathBuf> {
+    let mut keyed: Vec<(Option<SystemTime>, PathBuf)> = files
+        .into_iter()
+        .map(|file| {
+            let time = decoder
+                .read_metadata(&RawSource::from_path(&file))
+                .ok()
+                .and_then(|metadata| metadata.capture_time());
+            (time, file)
+        })
+        .collect();
+    keyed.sort_by(|(a_time, a_file), (b_time, b_file)| {
+        a_time.is_none().cmp(&b_time.is_none()).then(a_time.cmp(b_time)).then(a_file.cmp(b_file))
+    });
+    keyed.into_iter().map(|(_, file)| file).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decoder::{DecodeOptions, DecodedImage};
+    use crate::error::{DecodeError, DecodeStage};
+    use crate::metadata::ImageMetadata;
+    use crate::progress::Progress;
+    use image::DynamicImage;
+    use std::time::{Duration, Instant};
+
+    /// Reads the capture time from the file content, which holds seconds since the epoch
+    /// (or anything else for a file without one).
+    struct ContentTime;
+
+    impl RawDecoder for ContentTime {
+        fn name(&self) -> &'static str {
+            "test"
+        }
+
+        fn version(&self) -> Result<String, DecodeError> {
+            Ok("1".into())
+        }
+
+        fn decode(&self, _: &RawSource, _: &DecodeOptions, _: &Progress) -> Result<DecodedImage, DecodeError> {
+            unimplemented!()
+        }
+
+        fn decode_preview(&self, _: &RawSource) -> Result<DynamicImage, DecodeError> {
+            unimplemented!()
+        }
+
+        fn read_metadata(&self, source: &RawSource) -> Result<ImageMetadata, DecodeError> {
+            let bytes = source.bytes().map_err(|source| DecodeError::Io { stage: DecodeStage::Open, source })?;
+            let timestamp = std::str::from_utf8(&bytes).ok().and_then(|text| text.parse().ok());
+            Ok(ImageMetadata { timestamp, ..Default::default() })
+        }
+    }
+
+    /// A folder with four raw files, captured in the order b, d, a (c has no capture time),
+    /// and a few files and directories that are not raw files.
+    fn shoot(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("libraw_viewer_folder_{}_{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("sub.arw")).unwrap();
+        for (file, content) in [
+            ("a.arw", "300"),
+            ("b.ARW", "100"),
+            ("c.nef", "unknown"),
+            ("d.cr2", "200"),
+            ("notes.txt", "200"),
+            ("preview.jpg", "200"),
+        ] {
+            std::fs::write(dir.join(file), content).unwrap();
+        }
+        dir
+    }
+
+    fn open(dir: &Path, file: &str, order: SortOrder) -> Folder {
+        let decoder: Arc<dyn RawDecoder> = Arc::new(ContentTime);
+        Folder::open(&dir.join(file), order, &decoder).unwrap()
+    }
+
+    fn names(folder: &Folder) -> Vec<String> {
+        folder.files.iter().map(|file| file.file_name().unwrap().to_string_lossy().into_owned()).collect()
+    }
+
+    fn current_name(folder: &Folder) -> Option<String> {
+        folder.current().map(|file| file.file_name().unwrap().to_string_lossy().into_owned())
+    }
+
+    #[test]
+    fn lists_raw_files_by_name_and_selects_the_opened_one() {
+        let dir = shoot("by_name");
+        let folder = open(&dir, "c.nef", SortOrder::Name);
+        assert_eq!(names(&folder), ["a.arw", "b.ARW", "c.nef", "d.cr2"]);
+        assert_eq!(current_name(&folder).as_deref(), Some("c.nef"));
+        assert_eq!(folder.position(), (3, 4));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn includes_the_opened_file_whatever_its_name() {
+        let dir = shoot("opened");
+        let folder = open(&dir, "notes.txt", SortOrder::Name);
+        assert_eq!(names(&folder), ["a.arw", "b.ARW", "c.nef", "d.cr2", "notes.txt"]);
+        assert_eq!(folder.position(), (5, 5));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn step_stops_at_either_end() {
+        let dir = shoot("step");
+        let mut folder = open(&dir, "a.arw", SortOrder::Name);
+        assert_eq!(folder.step(-1), None);
+        assert_eq!(folder.step(1).unwrap(), dir.join("b.ARW"));
+        assert_eq!(folder.step(10).unwrap(), dir.join("d.cr2"));
+        assert_eq!(folder.step(1), None);
+        assert_eq!(folder.position(), (4, 4));
+        assert_eq!(folder.step(-2).unwrap(), dir.join("b.ARW"));
+        assert_eq!(folder.step(isize::MIN).unwrap(), dir.join("a.arw"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn neighbours_put_the_next_file_first() {
+        let dir = shoot("neighbours");
+        let mut folder = open(&dir, "a.arw", SortOrder::Name);
+        assert_eq!(folder.neighbours(), [dir.join("b.ARW")]);
+        folder.step(1);
+        assert_eq!(folder.neighbours(), [dir.join("c.nef"), dir.join("a.arw")]);
+        folder.step(10);
+        assert_eq!(folder.neighbours(), [dir.join("c.nef")]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn capture_time_sort_keeps_the_current_file() {
+        let dir = shoot("capture_time");
+        let mut folder = open(&dir, "a.arw", SortOrder::CaptureTime);
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !folder.poll() {
+            assert!(folder.is_sorting() && Instant::now() < deadline, "sorting did not finish");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        // Files without a capture time come last.
+        assert_eq!(names(&folder), ["b.ARW", "d.cr2", "a.arw", "c.nef"]);
+        assert_eq!(current_name(&folder).as_deref(), Some("a.arw"));
+        assert_eq!(folder.position(), (3, 4));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,157 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// Lower-case file extensions of the raw formats LibRaw can read.
+pub const RAW_EXTENSIONS: &[&str] = &[
+    "3fr", "ari", "arw", "bay", "cap", "cr2", "cr3", "crw", "dcr", "dcs", "dng", "drf", "eip", "erf",
+    "fff", "iiq", "k25", "kdc", "mdc", "mef", "mos", "mrw", "nef", "nrw", "orf", "pef", "ptx", "pxn",
+    "r3d", "raf", "raw", "rw2", "rwl", "rwz", "sr2", "srf", "srw", "sti", "x3f",
+];
+
+/// Lower-case extensions of ordinary image files. Plain TIFFs start like TIFF-based raws,
+/// so these are never sniffed.
+const IMAGE_EXTENSIONS: &[&str] = &["avif", "bmp", "exr", "gif", "heic", "jpeg", "jpg", "png", "tif", "tiff", "webp"];
+
+/// Number of leading bytes `sniff_raw_format` looks at.
+const SNIFF_LEN: usize = 32;
+
+/// Whether the path has one of the `RAW_EXTENSIONS` (case-insensitive).
+pub fn has_raw_extension(path: &Path) -> bool {
+    has_extension(path, RAW_EXTENSIONS)
+}
+
+fn has_extension(path: &Path, extensions: &[&str]) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| extensions.iter().any(|known| known.eq_ignore_ascii_case(ext)))
+        .unwrap_or(false)
+}
+
+/// Guesses the raw container from the first bytes of a file.
+/// Returns a short description of the container, or `None` if it does not look like a raw file.
+pub fn sniff_raw_format(header: &[u8]) -> Option<&'static str> {
+    let starts = |magic: &[u8]| header.starts_with(magic);
+    let at = |offset: usize, magic: &[u8]| header.get(offset..offset + magic.len()) == Some(magic);
+    if starts(b"FUJIFILMCCD-RAW") {
+        Some("Fujifilm RAF")
+    } else if at(4, b"ftypcrx ") {
+        Some("Canon CR3")
+    } else if at(6, b"HEAPCCDR") {
+        Some("Canon CRW")
+    } else if starts(b"IIU\0") {
+        Some("Panasonic RW2")
+    } else if starts(b"IIRO") || starts(b"IIRS") || starts(b"MMOR") {
+        Some("Olympus ORF")
+    } else if starts(b"\0MRM") {
+        Some("Minolta MRW")
+    } else if starts(b"FOVb") {
+        Some("Sigma X3F")
+    } else if starts(b"IIII") {
+        Some("Phase One IIQ")
+    } else if starts(b"II*\0") || starts(b"MM\0*") {
+        // ARW, CR2, NEF, DNG, PEF, SRW, 3FR and most others are TIFF containers.
+        Some("TIFF-based raw")
+    } else {
+        None
+    }
+}
+
+/// Reads the start of a file and runs `sniff_raw_format` on it.
+pub fn sniff_file(path: &Path) -> io::Result<Option<&'static str>> {
+    let mut header = Vec::with_capacity(SNIFF_LEN);
+    File::open(path)?.take(SNIFF_LEN as u64).read_to_end(&mut header)?;
+    Ok(sniff_raw_format(&header))
+}
+
+/// Whether a file should be handed to the decoder: either it has a raw extension,
+/// or its content looks like a raw file despite a wrong or missing extension.
+/// Files with an ordinary image extension (e.g. our own TIFF exports) are not raw files.
+pub fn is_raw_file(path: &Path) -> bool {
+    has_raw_extension(path) || (!has_extension(path, IMAGE_EXTENSIONS) && matches!(sniff_file(path), Ok(Some(_))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// A fresh directory for one test, removed by the caller.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("libraw_viewer_formats_{}_{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Byte-order mark, magic 42 and the offset of the first IFD, as every TIFF starts.
+    const TIFF_LITTLE_ENDIAN: &[u8] = b"II*\0\x08\0\0\0";
+
+    #[test]
+    fn sniffs_container_magic() {
+        let mut cr3 = vec![0, 0, 0, 0x18];
+        cr3.extend_from_slice(b"ftypcrx \0\0\0\x01");
+        let mut crw = b"II\x1a\0\0\0".to_vec();
+        crw.extend_from_slice(b"HEAPCCDR");
+        let cases: [(&[u8], &str); 10] = [
+            (b"FUJIFILMCCD-RAW 0201", "Fujifilm RAF"),
+            (&cr3, "Canon CR3"),
+            (&crw, "Canon CRW"),
+            (b"IIU\0\x08\0\0\0", "Panasonic RW2"),
+            (b"IIRO\x08\0\0\0", "Olympus ORF"),
+            (b"MMOR\0\0\0\x08", "Olympus ORF"),
+            (b"\0MRM\0\0\0\0", "Minolta MRW"),
+            (b"FOVb\0\0\0\0", "Sigma X3F"),
+            (b"IIII\x01\0\0\0", "Phase One IIQ"),
+            (b"MM\0*\0\0\0\x08", "TIFF-based raw"),
+        ];
+        for (header, format) in cases {
+            assert_eq!(sniff_raw_format(header), Some(format), "{:?}", header);
+        }
+    }
+
+    #[test]
+    fn plain_tiff_magic_is_indistinguishable_from_tiff_based_raws() {
+        // ARW, CR2, NEF and DNG all start like this, so the magic alone cannot rule out a plain TIFF.
+        assert_eq!(sniff_raw_format(TIFF_LITTLE_ENDIAN), Some("TIFF-based raw"));
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert_eq!(sniff_raw_format(b""), None);
+        assert_eq!(sniff_raw_format(b"II"), None);
+        assert_eq!(sniff_raw_format(b"\xff\xd8\xff\xe0\0\x10JFIF"), None);
+        assert_eq!(sniff_raw_format(b"\x89PNG\r\n\x1a\n"), None);
+        // CR3 magic must be at offset 4, not at the start.
+        assert_eq!(sniff_raw_format(b"ftypcrx \0\0\0\0"), None);
+    }
+
+    #[test]
+    fn raw_extensions_ignore_case() {
+        assert!(has_raw_extension(Path::new("IMG_0001.CR2")));
+        assert!(has_raw_extension(Path::new("dir/img_0001.nef")));
+        assert!(!has_raw_extension(Path::new("IMG_0001.jpg")));
+        assert!(!has_raw_extension(Path::new("nef")));
+    }
+
+    #[test]
+    fn plain_tiffs_are_not_raw_files_but_mislabeled_raws_are() {
+        let dir = temp_dir("is_raw_file");
+        let tiff = dir.join("export.tif");
+        let unlabeled = dir.join("DSC00001.bin");
+        let mislabeled = dir.join("DSC00002.NEF");
+        let text = dir.join("notes.txt");
+        std::fs::write(&tiff, TIFF_LITTLE_ENDIAN).unwrap();
+        std::fs::write(&unlabeled, TIFF_LITTLE_ENDIAN).unwrap();
+        std::fs::write(&mislabeled, b"not really a raw file").unwrap();
+        std::fs::write(&text, b"hello").unwrap();
+
+        assert!(!is_raw_file(&tiff));
+        assert!(is_raw_file(&unlabeled));
+        // The extension alone is enough; the decoder reports what is wrong with the content.
+        assert!(is_raw_file(&mislabeled));
+        assert!(!is_raw_file(&text));
+        assert!(!is_raw_file(&dir.join("missing.bin")));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
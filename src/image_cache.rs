@@ -0,0 +1,197 @@
+//! Recently viewed images kept in memory, so flipping between candidates does not re-decode.
+//!
+//! Entries hold the decoded buffer and, once shown, its uploaded textures. They are keyed by
+//! file, backend and decode options; when the total outgrows the memory budget the least
+//! recently viewed entries are dropped.
+
+use crate::decoder::DecodeOptions;
+use crate::metadata::ImageMetadata;
+use crate::tiles::TiledTexture;
+use image::DynamicImage;
+use std::path::{Path, PathBuf};
+
+/// Used unless LIBRAW_VIEWER_MEMORY_MB says otherwise.
+pub const DEFAULT_MAX_BYTES: usize = 1024 * 1024 * 1024;
+
+/// Everything that determines the pixels of a decode.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageKey {
+    pub path: PathBuf,
+    pub backend: &'static str,
+    pub options: DecodeOptions,
+}
+
+impl ImageKey {
+    pub fn new(path: &Path, backend: &'static str, options: &DecodeOptions) -> Self {
+        Self { path: path.to_path_buf(), backend, options: options.clone() }
+    }
+}
+
+/// A decoded image as it was last shown.
+pub struct CachedImage {
+    /// The decode rotated by `quarter_turns`.
+    pub image: DynamicImage,
+    pub metadata: ImageMetadata,
+    pub quarter_turns: i32,
+    /// `None` for images that were decoded but not uploaded yet.
+    pub texture: Option<TiledTexture>,
+}
+
+impl CachedImage {
+    fn byte_size(&self) -> usize {
+        self.image.as_bytes().len() + self.texture.as_ref().map_or(0, TiledTexture::byte_size)
+    }
+}
+
+struct Entry {
+    key: ImageKey,
+    image: CachedImage,
+    bytes: usize,
+}
+
+/// The cache itself. Lookups are linear, which is fine for the handful of images a budget holds.
+pub struct ImageCache {
+    /// Least recently viewed first.
+    entries: Vec<Entry>,
+    max_bytes: usize,
+    used: usize,
+}
+
+impl ImageCache {
+    pub fn new(max_bytes: usize) -> Self {
+        Self { entries: Vec::new(), max_bytes, used: 0 }
+    }
+
+    /// The budget from LIBRAW_VIEWER_MEMORY_MB, or `DEFAULT_MAX_BYTES`. Zero disables the cache.
+    pub fn max_bytes_from_env() -> usize {
+        std::env::var("LIBRAW_VIEWER_MEMORY_MB")
+            .ok()
+            .and_then(|mb| mb.trim().parse::<usize>().ok())
+            .map_or(DEFAULT_MAX_BYTES, |mb| mb.saturating_mul(1024 * 1024))
+    }
+
+    /// Bytes held by the cached buffers and textures.
+    pub fn size(&self) -> usize {
+        self.used
+    }
+
+    pub fn max_bytes(&self) -> usize {
+        self.max_bytes
+    }
+
+    pub fn contains(&self, key: &ImageKey) -> bool {
+        self.entries.iter().any(|entry| entry.key == *key)
+    }
+
+    /// Removes and returns the image for `key`; it is expected back through `insert`
+    /// when something else is shown.
+    pub fn take(&mut self, key: &ImageKey) -> Option<CachedImage> {
+        let index = self.entries.iter().position(|entry| entry.key == *key)?;
+        let entry = self.entries.remove(index);
+        self.used -= entry.bytes;
+        Some(entry.image)
+    }
+
+    /// Stores `image` as the most recently viewed entry, evicting the least recently viewed
+    /// ones to stay within the budget. Images larger than the whole budget are not kept.
+    pub fn insert(&mut self, key: ImageKey, image: CachedImage) {
+        let _ = self.take(&key);
+        let bytes = image.byte_size();
+        if bytes > self.max_bytes {
+            return;
+        }
+        while self.used + bytes > self.max_bytes && !self.entries.is_empty() {
+            let evicted = self.entries.remove(0);
+            self.used -= evicted.bytes;
+        }
+        self.used += bytes;
+        self.entries.push(Entry { key, image, bytes });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbImage;
+
+    fn key(name: &str) -> ImageKey {
+        ImageKey::new(Path::new(name), "LibRaw", &DecodeOptions::default())
+    }
+
+    /// An image of `bytes` bytes (a multiple of 3).
+    fn image(bytes: u32) -> CachedImage {
+        CachedImage {
+            image: DynamicImage::ImageRgb8(RgbImage::new(bytes / 3, 1)),
+            metadata: ImageMetadata::default(),
+            quarter_turns: 0,
+            texture: None,
+        }
+    }
+
+    #[test]
+    fn stays_within_the_budget_by_dropping_the_least_recently_viewed() {
+        let mut cache = ImageCache::new(300);
+        cache.insert(key("a"), image(120));
+        cache.insert(key("b"), image(120));
+        assert_eq!(cache.size(), 240);
+
+        // Viewing `a` again makes `b` the oldest entry.
+        let a = cache.take(&key("a")).unwrap();
+        cache.insert(key("a"), a);
+        cache.insert(key("c"), image(120));
+
+        assert_eq!(cache.size(), 240);
+        assert!(cache.contains(&key("a")));
+        assert!(!cache.contains(&key("b")));
+        assert!(cache.contains(&key("c")));
+    }
+
+    #[test]
+    fn evicts_as_many_entries_as_needed() {
+        let mut cache = ImageCache::new(300);
+        cache.insert(key("a"), image(90));
+        cache.insert(key("b"), image(90));
+        cache.insert(key("c"), image(90));
+        cache.insert(key("d"), image(210));
+        assert_eq!(cache.size(), 300);
+        assert!(!cache.contains(&key("a")) && !cache.contains(&key("b")));
+        assert!(cache.contains(&key("c")) && cache.contains(&key("d")));
+    }
+
+    #[test]
+    fn images_larger_than_the_budget_are_not_kept() {
+        let mut cache = ImageCache::new(300);
+        cache.insert(key("a"), image(120));
+        cache.insert(key("huge"), image(303));
+        assert!(!cache.contains(&key("huge")));
+        assert!(cache.contains(&key("a")));
+        assert_eq!(cache.size(), 120);
+    }
+
+    #[test]
+    fn reinserting_a_key_replaces_the_entry() {
+        let mut cache = ImageCache::new(300);
+        cache.insert(key("a"), image(120));
+        cache.insert(key("a"), image(60));
+        assert_eq!(cache.size(), 60);
+        assert_eq!(cache.take(&key("a")).unwrap().image.as_bytes().len(), 60);
+        assert_eq!(cache.size(), 0);
+        assert!(cache.take(&key("a")).is_none());
+    }
+
+    #[test]
+    fn keys_include_the_backend_and_options() {
+        let mut cache = ImageCache::new(300);
+        cache.insert(key("a"), image(30));
+        let full = DecodeOptions { mode: crate::decoder::DecodeMode::Full, ..DecodeOptions::default() };
+        assert!(!cache.contains(&ImageKey::new(Path::new("a"), "LibRaw", &full)));
+        assert!(!cache.contains(&ImageKey::new(Path::new("a"), "rawloader", &DecodeOptions::default())));
+    }
+
+    #[test]
+    fn a_zero_budget_disables_the_cache() {
+        let mut cache = ImageCache::new(0);
+        cache.insert(key("a"), image(3));
+        assert!(!cache.contains(&key("a")));
+    }
+}
@@ -0,0 +1,147 @@
+//! The thumbnail grid of a folder.
+
+use crate::decoder::RawDecoder;
+use crate::folder::Folder;
+use crate::thumbnails::{Thumbnail, ThumbnailCache, THUMBNAIL_SIDE};
+use eframe::egui::{self, Align2, Color32, FontId, Key, Pos2, Rect, Sense, Vec2};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Range of the thumbnail size slider, in points.
+pub const MIN_THUMBNAIL_SIZE: f32 = 64.0;
+pub const MAX_THUMBNAIL_SIZE: f32 = THUMBNAIL_SIDE as f32;
+/// Room below each thumbnail for the file name.
+const LABEL_HEIGHT: f32 = 18.0;
+
+/// State of the grid that outlives a frame.
+pub struct LibraryView {
+    /// Side of a thumbnail cell in points.
+    pub thumbnail_size: f32,
+    /// Set when the selection moved by keyboard, so the grid scrolls to keep it in view.
+    reveal_selection: bool,
+    /// Scroll offset and height of the grid in the last frame.
+    scroll_offset: f32,
+    view_height: f32,
+}
+
+impl Default for LibraryView {
+    fn default() -> Self {
+        Self { thumbnail_size: 160.0, reveal_selection: false, scroll_offset: 0.0, view_height: 0.0 }
+    }
+}
+
+impl LibraryView {
+    /// Shows the folder as a grid; the current file of `folder` is the selection.
+    /// Only visible cells request thumbnails. Returns the file to open when one is
+    /// double-clicked or Enter is pressed.
+    pub fn show(
+        &mut self,
+        ui: &mut egui::Ui,
+        folder: &mut Folder,
+        thumbnails: &mut ThumbnailCache,
+        decoder: &Arc<dyn RawDecoder>,
+    ) -> Option<PathBuf> {
+        let count = folder.files().len();
+        if count == 0 {
+            ui.label("No raw files in this folder");
+            return None;
+        }
+        let spacing = ui.spacing().item_spacing;
+        let cell = Vec2::new(self.thumbnail_size, self.thumbnail_size + LABEL_HEIGHT);
+        let columns = ((ui.available_width() + spacing.x) / (cell.x + spacing.x)).floor().max(1.0) as usize;
+        let rows = count.div_ceil(columns);
+        let mut open = None;
+
+        if !ui.ctx().wants_keyboard_input() {
+            let (left, right, up, down, enter) = ui.input(|input| {
+                (
+                    input.key_pressed(Key::ArrowLeft),
+                    input.key_pressed(Key::ArrowRight),
+                    input.key_pressed(Key::ArrowUp),
+                    input.key_pressed(Key::ArrowDown),
+                    input.key_pressed(Key::Enter),
+                )
+            });
+            let columns = columns as isize;
+            let delta = right as isize - left as isize + (down as isize - up as isize) * columns;
+            if delta != 0 {
+                let target = (folder.index() as isize + delta).clamp(0, count as isize - 1);
+                folder.select(target as usize);
+                self.reveal_selection = true;
+            }
+            if enter {
+                open = folder.current().map(Path::to_path_buf);
+            }
+        }
+
+        let mut scroll = egui::ScrollArea::vertical().auto_shrink([false, false]);
+        if self.reveal_selection {
+            let top = (folder.index() / columns) as f32 * (cell.y + spacing.y);
+            let offset = if top < self.scroll_offset {
+                top
+            } else if top + cell.y > self.scroll_offset + self.view_height {
+                top + cell.y - self.view_height
+            } else {
+                self.scroll_offset
+            };
+            scroll = scroll.vertical_scroll_offset(offset);
+            self.reveal_selection = false;
+        }
+        let output = scroll.show_rows(ui, cell.y, rows, |ui, visible_rows| {
+            for row in visible_rows {
+                ui.horizontal(|ui| {
+                    for index in row * columns..((row + 1) * columns).min(count) {
+                        let path = folder.files()[index].clone();
+                        let (rect, response) = ui.allocate_exact_size(cell, Sense::click());
+                        let thumbnail = thumbnails.get(&path, decoder);
+                        paint_cell(ui, rect, &path, thumbnail, index == folder.index());
+                        let hover = match thumbnail {
+                            Thumbnail::Failed(e) => format!("{}\n{}", path.display(), e),
+                            _ => path.display().to_string(),
+                        };
+                        let response = response.on_hover_text(hover);
+                        if response.clicked() {
+                            folder.select(index);
+                        }
+                        if response.double_clicked() {
+                            folder.select(index);
+                            open = Some(path);
+                        }
+                    }
+                });
+            }
+        });
+        self.scroll_offset = output.state.offset.y;
+        self.view_height = output.inner_rect.height();
+        open
+    }
+}
+
+/// Draws a thumbnail fitted into the square top of `rect`, with the file name below.
+fn paint_cell(ui: &egui::Ui, rect: Rect, path: &Path, thumbnail: &Thumbnail, selected: bool) {
+    let painter = ui.painter().with_clip_rect(rect.expand(2.0).intersect(ui.clip_rect()));
+    let visuals = ui.visuals();
+    if selected {
+        painter.rect_filled(rect.expand(2.0), 4.0, visuals.selection.bg_fill);
+    }
+    let square = Rect::from_min_size(rect.min, Vec2::splat(rect.width()));
+    match thumbnail {
+        Thumbnail::Ready(texture) => {
+            let size = texture.size_vec2();
+            let scale = (square.width() / size.x).min(square.height() / size.y);
+            let uv = Rect::from_min_max(Pos2::ZERO, Pos2::new(1.0, 1.0));
+            painter.image(texture.id(), Rect::from_center_size(square.center(), size * scale), uv, Color32::WHITE);
+        }
+        Thumbnail::Pending => {
+            let color = visuals.weak_text_color();
+            painter.text(square.center(), Align2::CENTER_CENTER, "...", FontId::proportional(14.0), color);
+        }
+        Thumbnail::Failed(_) => {
+            let color = visuals.warn_fg_color;
+            painter.text(square.center(), Align2::CENTER_CENTER, "No preview", FontId::proportional(12.0), color);
+        }
+    }
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let label = Pos2::new(rect.center().x, rect.bottom() - LABEL_HEIGHT / 2.0);
+    painter.text(label, Align2::CENTER_CENTER, name, FontId::proportional(12.0), visuals.text_color());
+}
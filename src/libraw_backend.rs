@@ -0,0 +1,622 @@
+//! The LibRaw decoder backend.
+
+use crate::decoder::{BitDepth, DecodeMode, DecodeOptions, DecodedImage, RawDecoder};
+use crate::error::{DecodeError, DecodeStage, LibRawError};
+use crate::fallback::extract_embedded_jpeg;
+use crate::ffi::{self, LibRawApi, LibRawData, LibRawProcessedImage, LrvMetadata, LrvParams};
+use crate::metadata::{GpsInfo, ImageMetadata};
+use crate::orientation::apply_flip;
+use crate::params::{ProcessingParams, WhiteBalance};
+use crate::progress::Progress;
+use crate::source::RawSource;
+use image::{DynamicImage, ImageBuffer};
+use libc::{c_char, c_int, c_void};
+use std::ffi::CStr;
+use std::path::Path;
+use std::ptr::NonNull;
+use std::slice;
+use std::sync::Arc;
+
+/// `LibRawProcessedImage::type_` of an embedded JPEG (`LIBRAW_IMAGE_JPEG`).
+const LIBRAW_IMAGE_JPEG: c_int = 1;
+/// `LibRawProcessedImage::type_` of an uncompressed bitmap (`LIBRAW_IMAGE_BITMAP`).
+const LIBRAW_IMAGE_BITMAP: c_int = 2;
+/// `enum LibRaw_progress` is a bit per step; `LIBRAW_PROGRESS_STRETCH` (bit 19) is the last
+/// step of `dcraw_process`, so bits 0 to 19 cover a whole render.
+const PROGRESS_STEPS: f32 = 20.0;
+
+/// Decodes through LibRaw, linked at build time or loaded at runtime.
+pub struct LibRawDecoder;
+
+impl RawDecoder for LibRawDecoder {
+    fn name(&self) -> &'static str {
+        "LibRaw"
+    }
+
+    fn version(&self) -> Result<String, DecodeError> {
+        libraw_version()
+    }
+
+    fn decode(
+        &self,
+        source: &RawSource,
+        options: &DecodeOptions,
+        progress: &Progress,
+    ) -> Result<DecodedImage, DecodeError> {
+        decode_file(source, options, progress)
+    }
+
+    fn decode_preview(&self, source: &RawSource) -> Result<DynamicImage, DecodeError> {
+        decode_preview(source)
+    }
+
+    fn read_metadata(&self, source: &RawSource) -> Result<ImageMetadata, DecodeError> {
+        read_metadata(source)
+    }
+}
+
+/// An owned LibRaw handle. The handle is released with `libraw_close` on drop,
+/// so every early return (or panic) after `LibRaw::new` cleans up after itself.
+pub struct LibRaw {
+    api: &'static LibRawApi,
+    raw: NonNull<LibRawData>,
+    /// The buffer passed to `libraw_open_buffer`; LibRaw reads from it until the handle is dropped.
+    _buffer: Option<Arc<[u8]>>,
+    /// The data pointer registered with `libraw_set_progress_handler`. Boxed so its address
+    /// stays the same when the handle moves.
+    _progress: Option<Box<ProgressContext>>,
+}
+
+/// What the progress callback needs: the API (for step names) and where to report.
+struct ProgressContext {
+    api: &'static LibRawApi,
+    progress: Progress,
+}
+
+impl LibRaw {
+    /// Allocates a new LibRaw handle.
+    /// `libraw_init` only fails when it cannot allocate, so that is reported as out of memory.
+    pub fn new() -> Result<Self, DecodeError> {
+        let api = ffi::api().map_err(DecodeError::LibraryUnavailable)?;
+        let raw = unsafe { (api.init)(0) };
+        NonNull::new(raw).map(|raw| Self { api, raw, _buffer: None, _progress: None }).ok_or(DecodeError::LibRaw {
+            stage: DecodeStage::Init,
+            error: LibRawError::OutOfMemory,
+        })
+    }
+
+    fn as_ptr(&self) -> *mut LibRawData {
+        self.raw.as_ptr()
+    }
+
+    /// Opens a file or buffer and parses its headers.
+    pub fn open(&mut self, source: &RawSource) -> Result<(), DecodeError> {
+        match source {
+            RawSource::Path(path) => self.open_path(path),
+            RawSource::Buffer(data) => self.open_buffer(data.clone()),
+        }
+    }
+
+    /// Opens a file and parses its headers.
+    /// The path is passed in the OS encoding, so non-UTF-8 names work on Unix.
+    #[cfg(unix)]
+    pub fn open_path(&mut self, path: &Path) -> Result<(), DecodeError> {
+        use std::os::unix::ffi::OsStrExt;
+        let c_path = std::ffi::CString::new(path.as_os_str().as_bytes())
+            .map_err(|e| DecodeError::InvalidPath(e.to_string()))?;
+        let ret = unsafe { (self.api.open_file)(self.as_ptr(), c_path.as_ptr()) };
+        check(DecodeStage::Open, ret)
+    }
+
+    /// Opens a file and parses its headers, passing the path as UTF-16.
+    #[cfg(all(windows, target_env = "msvc"))]
+    pub fn open_path(&mut self, path: &Path) -> Result<(), DecodeError> {
+        use std::os::windows::ffi::OsStrExt;
+        let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+        if wide[..wide.len() - 1].contains(&0) {
+            return Err(DecodeError::InvalidPath(format!("{} contains a NUL character", path.display())));
+        }
+        let ret = unsafe { (self.api.open_wfile)(self.as_ptr(), wide.as_ptr()) };
+        check(DecodeStage::Open, ret)
+    }
+
+    /// Opens a file and parses its headers.
+    /// Without `libraw_open_wfile` the path must be representable as UTF-8.
+    #[cfg(not(any(unix, all(windows, target_env = "msvc"))))]
+    pub fn open_path(&mut self, path: &Path) -> Result<(), DecodeError> {
+        let utf8 = path
+            .to_str()
+            .ok_or_else(|| DecodeError::InvalidPath(format!("{} is not valid Unicode", path.display())))?;
+        let c_path = std::ffi::CString::new(utf8).map_err(|e| DecodeError::InvalidPath(e.to_string()))?;
+        let ret = unsafe { (self.api.open_file)(self.as_ptr(), c_path.as_ptr()) };
+        check(DecodeStage::Open, ret)
+    }
+
+    /// Opens a raw file that is already in memory and parses its headers.
+    /// The handle keeps `data` alive, since LibRaw reads from it again when unpacking.
+    pub fn open_buffer(&mut self, data: Arc<[u8]>) -> Result<(), DecodeError> {
+        let ret = unsafe { (self.api.open_buffer)(self.as_ptr(), data.as_ptr() as *const c_void, data.len()) };
+        // Replacing the previous buffer is safe: opening resets LibRaw's input.
+        self._buffer = Some(data);
+        check(DecodeStage::Open, ret)
+    }
+
+    /// Reports LibRaw's processing steps to `progress`, and stops them once it is cancelled.
+    pub fn set_progress(&mut self, progress: Progress) {
+        let context = Box::new(ProgressContext { api: self.api, progress });
+        let data = &*context as *const ProgressContext as *mut c_void;
+        unsafe { (self.api.set_progress_handler)(self.as_ptr(), progress_callback, data) };
+        self._progress = Some(context);
+    }
+
+    /// Reads the raw sensor data of the opened file.
+    pub fn unpack(&mut self) -> Result<(), DecodeError> {
+        let ret = unsafe { (self.api.unpack)(self.as_ptr()) };
+        check(DecodeStage::Unpack, ret)
+    }
+
+    /// The shooting metadata of the opened file.
+    pub fn metadata(&self) -> ImageMetadata {
+        let mut raw_meta = std::mem::MaybeUninit::<LrvMetadata>::uninit();
+        // lrv_get_metadata fills every field of the struct.
+        let m = unsafe {
+            ffi::lrv_get_metadata(self.as_ptr(), raw_meta.as_mut_ptr());
+            raw_meta.assume_init()
+        };
+        let gps = (m.gpsparsed != 0).then(|| {
+            let degrees = |dms: [f32; 3]| dms[0] as f64 + dms[1] as f64 / 60.0 + dms[2] as f64 / 3600.0;
+            let sign = |reference: c_char, negative: u8| if reference as u8 == negative { -1.0 } else { 1.0 };
+            GpsInfo {
+                latitude: sign(m.latref, b'S') * degrees(m.latitude),
+                longitude: sign(m.longref, b'W') * degrees(m.longitude),
+                altitude: if m.altref == 1 { -m.altitude } else { m.altitude },
+            }
+        });
+        ImageMetadata {
+            make: c_chars_to_string(&m.make),
+            model: c_chars_to_string(&m.model),
+            lens: c_chars_to_string(&m.lens),
+            serial: c_chars_to_string(&m.serial),
+            artist: c_chars_to_string(&m.artist),
+            iso: m.iso_speed,
+            shutter: m.shutter,
+            aperture: m.aperture,
+            focal_length: m.focal_len,
+            timestamp: (m.timestamp != 0).then_some(m.timestamp),
+            gps,
+            flash_fired: m.flash_used > 0.0,
+            flip: m.flip,
+        }
+    }
+
+    /// Reads the embedded thumbnail of the opened file.
+    pub fn unpack_thumb(&mut self) -> Result<(), DecodeError> {
+        let ret = unsafe { (self.api.unpack_thumb)(self.as_ptr()) };
+        check(DecodeStage::Thumbnail, ret)
+    }
+
+    /// Sets the number of bits per channel of the processed output (8 or 16).
+    pub fn set_output_bps(&mut self, bps: c_int) {
+        unsafe { (self.api.set_output_bps)(self.as_ptr(), bps) }
+    }
+
+    /// Makes `dcraw_process` skip demosaicing and output one pixel per 2x2 Bayer block.
+    pub fn set_half_size(&mut self, half_size: bool) {
+        unsafe { ffi::lrv_set_half_size(self.as_ptr(), half_size as c_int) }
+    }
+
+    /// Copies the processing parameters into `libraw_output_params_t` for the next `dcraw_process`.
+    pub fn set_params(&mut self, params: &ProcessingParams) {
+        let (use_camera_wb, use_auto_wb, user_mul) = match params.white_balance {
+            WhiteBalance::Daylight => (0, 0, [0.0; 4]),
+            WhiteBalance::Camera => (1, 0, [0.0; 4]),
+            WhiteBalance::Auto => (0, 1, [0.0; 4]),
+            WhiteBalance::Custom(mul) => (0, 0, mul),
+        };
+        let exposure_ev = params.exposure_ev.clamp(-2.0, 3.0);
+        let raw_params = LrvParams {
+            use_camera_wb,
+            use_auto_wb,
+            user_mul,
+            user_qual: params.demosaic.user_qual(),
+            exp_correc: (exposure_ev != 0.0) as c_int,
+            // LibRaw takes a linear multiplier between 0.25 (-2 EV) and 8 (+3 EV).
+            exp_shift: 2f32.powf(exposure_ev),
+            exp_preser: params.exposure_preserve.clamp(0.0, 1.0),
+            highlight: params.highlight.code(),
+            threshold: params.noise_threshold.max(0.0),
+            bright: params.brightness,
+            no_auto_bright: (!params.auto_bright) as c_int,
+            gamm: params.gamma.gamm(),
+            output_color: params.color_space.code(),
+        };
+        unsafe { ffi::lrv_set_params(self.as_ptr(), &raw_params) }
+    }
+
+    /// Keeps `dcraw_process` output in sensor orientation; we orient both previews
+    /// and renders ourselves so the two paths behave the same.
+    fn disable_auto_flip(&mut self) {
+        unsafe { ffi::lrv_set_user_flip(self.as_ptr(), 0) }
+    }
+
+    /// Runs the dcraw processing pipeline (demosaic, white balance, color conversion).
+    pub fn dcraw_process(&mut self) -> Result<(), DecodeError> {
+        let ret = unsafe { (self.api.dcraw_process)(self.as_ptr()) };
+        check(DecodeStage::Process, ret)
+    }
+
+    /// Copies the unpacked thumbnail into memory.
+    pub fn make_mem_thumb(&self) -> Result<ProcessedImage, DecodeError> {
+        let mut err: c_int = 0;
+        let image = unsafe { (self.api.dcraw_make_mem_thumb)(self.as_ptr(), &mut err) };
+        ProcessedImage::from_raw(self.api, image, err).map_err(|err| DecodeError::libraw(DecodeStage::Thumbnail, err))
+    }
+
+    /// Copies the processed image into memory.
+    pub fn make_mem_image(&self) -> Result<ProcessedImage, DecodeError> {
+        let mut err: c_int = 0;
+        let image = unsafe { (self.api.dcraw_make_mem_image)(self.as_ptr(), &mut err) };
+        ProcessedImage::from_raw(self.api, image, err).map_err(|err| DecodeError::libraw(DecodeStage::Image, err))
+    }
+}
+
+/// Converts a NUL-terminated C string field into an owned, trimmed `String`.
+fn c_chars_to_string(chars: &[c_char]) -> String {
+    let bytes: Vec<u8> = chars.iter().map(|&c| c as u8).collect();
+    CStr::from_bytes_until_nul(&bytes)
+        .map(|s| s.to_string_lossy().trim().to_string())
+        .unwrap_or_default()
+}
+
+/// Called by LibRaw at the start and end of each processing step (and during long ones).
+/// Returning non-zero makes the running LibRaw call fail with `LIBRAW_CANCELLED_BY_CALLBACK`.
+unsafe extern "C" fn progress_callback(data: *mut c_void, stage: c_int, iteration: c_int, expected: c_int) -> c_int {
+    let context = unsafe { &*(data as *const ProgressContext) };
+    let name = unsafe { (context.api.strprogress)(stage) };
+    let name = if name.is_null() {
+        String::new()
+    } else {
+        unsafe { CStr::from_ptr(name) }.to_string_lossy().into_owned()
+    };
+    let step = if stage == 0 { 0.0 } else { (stage as u32).trailing_zeros() as f32 };
+    let within = if expected > 0 { iteration as f32 / expected as f32 } else { 0.0 };
+    context.progress.update(&name, (step + within) / PROGRESS_STEPS);
+    context.progress.is_cancelled() as c_int
+}
+
+/// Turns a LibRaw return code into a `Result`.
+fn check(stage: DecodeStage, ret: c_int) -> Result<(), DecodeError> {
+    if ret != 0 {
+        return Err(DecodeError::libraw(stage, ret));
+    }
+    Ok(())
+}
+
+impl Drop for LibRaw {
+    fn drop(&mut self) {
+        unsafe { (self.api.close)(self.as_ptr()) }
+    }
+}
+
+/// An image allocated by LibRaw (`libraw_dcraw_make_mem_*`).
+/// The memory is returned with `libraw_dcraw_clear_mem` on drop.
+pub struct ProcessedImage {
+    api: &'static LibRawApi,
+    image: NonNull<LibRawProcessedImage>,
+}
+
+impl ProcessedImage {
+    fn from_raw(api: &'static LibRawApi, image: *mut LibRawProcessedImage, err: c_int) -> Result<Self, c_int> {
+        match NonNull::new(image) {
+            Some(image) if err == 0 => Ok(Self { api, image }),
+            Some(image) => {
+                // Do not leak an image LibRaw handed us together with an error.
+                unsafe { (api.dcraw_clear_mem)(image.as_ptr()) };
+                Err(err)
+            }
+            // A null image without an error code should not happen; report it as unspecified.
+            None if err == 0 => Err(LibRawError::Unspecified.code()),
+            None => Err(err),
+        }
+    }
+
+    fn header(&self) -> &LibRawProcessedImage {
+        unsafe { self.image.as_ref() }
+    }
+
+    pub fn image_type(&self) -> c_int {
+        self.header().type_
+    }
+
+    pub fn colors(&self) -> c_int {
+        self.header().colors
+    }
+
+    pub fn bits(&self) -> c_int {
+        self.header().bits
+    }
+
+    pub fn width(&self) -> u32 {
+        self.header().width as u32
+    }
+
+    pub fn height(&self) -> u32 {
+        self.header().height as u32
+    }
+
+    /// The pixel (or JPEG) payload of the image.
+    pub fn data(&self) -> &[u8] {
+        let header = self.header();
+        if header.data.is_null() || header.data_size <= 0 {
+            return &[];
+        }
+        // `data` is a flexible array member that lives as long as the image itself.
+        unsafe { slice::from_raw_parts(header.data as *const u8, header.data_size as usize) }
+    }
+}
+
+impl Drop for ProcessedImage {
+    fn drop(&mut self) {
+        unsafe { (self.api.dcraw_clear_mem)(self.image.as_ptr()) }
+    }
+}
+
+/// Helper function to extract image data from a processed image.
+/// JPEG thumbnails are decoded to 8-bit RGB; bitmaps are converted to RGB
+/// and keep their bit depth.
+fn extract_image_data(stage: DecodeStage, image: &ProcessedImage) -> Result<DynamicImage, DecodeError> {
+    match image.image_type() {
+        LIBRAW_IMAGE_JPEG => decode_jpeg(stage, image.data()),
+        LIBRAW_IMAGE_BITMAP => bitmap_to_rgb(stage, image),
+        other => Err(DecodeError::UnsupportedFormat {
+            stage,
+            detail: format!("unknown LibRaw image type {}", other),
+        }),
+    }
+}
+
+/// Decodes an embedded JPEG preview into 8-bit RGB.
+fn decode_jpeg(stage: DecodeStage, data: &[u8]) -> Result<DynamicImage, DecodeError> {
+    let decoded = image::load_from_memory_with_format(data, image::ImageFormat::Jpeg)
+        .map_err(|source| DecodeError::EmbeddedImage { stage, source })?;
+    Ok(DynamicImage::ImageRgb8(decoded.into_rgb8()))
+}
+
+/// Converts an 8- or 16-bit bitmap with 1, 3 or 4 channels into RGB of the same depth.
+fn bitmap_to_rgb(stage: DecodeStage, image: &ProcessedImage) -> Result<DynamicImage, DecodeError> {
+    let data = image.data();
+    let width = image.width();
+    let height = image.height();
+    let colors = image.colors() as usize;
+    let bytes_per_sample = match image.bits() {
+        8 => 1,
+        16 => 2,
+        bits => {
+            return Err(DecodeError::UnsupportedFormat {
+                stage,
+                detail: format!("{} bits per sample", bits),
+            })
+        }
+    };
+    if !matches!(colors, 1 | 3 | 4) {
+        return Err(DecodeError::UnsupportedFormat {
+            stage,
+            detail: format!("{} color channels", colors),
+        });
+    }
+    let expected_size = (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|v| v.checked_mul(colors * bytes_per_sample))
+        .ok_or(DecodeError::DimensionsTooLarge { stage, width, height })?;
+    if data.len() != expected_size {
+        return Err(DecodeError::SizeMismatch { stage, expected: expected_size, actual: data.len() });
+    }
+    let too_large = DecodeError::DimensionsTooLarge { stage, width, height };
+    if bytes_per_sample == 1 {
+        let rgb = samples_to_rgb(data, colors);
+        ImageBuffer::from_raw(width, height, rgb).map(DynamicImage::ImageRgb8).ok_or(too_large)
+    } else {
+        // 16-bit samples are in native byte order.
+        let samples: Vec<u16> = data
+            .chunks_exact(2)
+            .map(|bytes| u16::from_ne_bytes([bytes[0], bytes[1]]))
+            .collect();
+        let rgb = samples_to_rgb(&samples, colors);
+        ImageBuffer::from_raw(width, height, rgb).map(DynamicImage::ImageRgb16).ok_or(too_large)
+    }
+}
+
+/// Expands gray or drops the fourth channel so every pixel has exactly three samples.
+fn samples_to_rgb<T: Copy>(samples: &[T], colors: usize) -> Vec<T> {
+    match colors {
+        3 => samples.to_vec(),
+        1 => samples.iter().flat_map(|&gray| [gray, gray, gray]).collect(),
+        _ => samples
+            .chunks_exact(colors)
+            .flat_map(|pixel| [pixel[0], pixel[1], pixel[2]])
+            .collect(),
+    }
+}
+
+/// Extracts the embedded preview of a raw file without unpacking or processing
+/// the sensor data, which makes it fast enough for browsing many files.
+/// The preview is rotated to the camera orientation.
+fn decode_preview(source: &RawSource) -> Result<DynamicImage, DecodeError> {
+    let mut raw = match LibRaw::new() {
+        Err(DecodeError::LibraryUnavailable(reason)) => return fallback_preview(source, reason),
+        result => result?,
+    };
+    raw.open(source)?;
+    let flip = raw.metadata().flip;
+    extract_preview(&mut raw).map(|image| apply_flip(image, flip))
+}
+
+/// Unpacks and converts the thumbnail of an already opened file.
+fn extract_preview(raw: &mut LibRaw) -> Result<DynamicImage, DecodeError> {
+    raw.unpack_thumb()?;
+    let thumb = raw.make_mem_thumb()?;
+    extract_image_data(DecodeStage::Thumbnail, &thumb)
+}
+
+/// Unpacks and processes the sensor data of an already opened file into RGB.
+fn render(
+    raw: &mut LibRaw,
+    half_size: bool,
+    depth: BitDepth,
+    params: &ProcessingParams,
+) -> Result<DynamicImage, DecodeError> {
+    raw.set_half_size(half_size);
+    raw.unpack()?;
+    raw.set_params(params);
+    raw.set_output_bps(depth.bits());
+    raw.disable_auto_flip();
+    raw.dcraw_process()?;
+    let image = raw.make_mem_image()?;
+    extract_image_data(DecodeStage::Image, &image)
+}
+
+/// Decodes a raw file using LibRaw.
+/// It first tries to extract the embedded thumbnail. If there is none,
+/// it falls back to unpacking and processing the full image at 8 bits per channel.
+fn decode_preview_or_render(
+    raw: &mut LibRaw,
+    source: &RawSource,
+    progress: &Progress,
+) -> Result<DynamicImage, DecodeError> {
+    match extract_preview(raw) {
+        // No usable thumbnail; try full image extraction.
+        Err(e) if is_missing_thumbnail(&e) => render(raw, false, BitDepth::Eight, &ProcessingParams::default()),
+        Err(e) if e.stage() == Some(DecodeStage::Thumbnail) && is_fatal(&e) && !e.is_cancelled() => {
+            // A corrupt thumbnail leaves the handle unusable, but the sensor data may still be
+            // fine; LibRaw needs a fresh handle after a fatal error.
+            *raw = LibRaw::new()?;
+            raw.set_progress(progress.clone());
+            raw.open(source)?;
+            render(raw, false, BitDepth::Eight, &ProcessingParams::default())
+        }
+        result => result,
+    }
+}
+
+/// Decodes any raw format LibRaw supports with the given mode.
+/// LibRaw identifies the format from the file content, so the extension does not matter.
+/// Embedded previews are always 8-bit and unaffected by the processing parameters.
+/// Without LibRaw, previews are still extracted in pure Rust (without metadata or orientation).
+fn decode_file(source: &RawSource, options: &DecodeOptions, progress: &Progress) -> Result<DecodedImage, DecodeError> {
+    // A decode may be superseded before its worker gets to run.
+    if progress.is_cancelled() {
+        return Err(DecodeError::Cancelled);
+    }
+    let mut raw = match LibRaw::new() {
+        Err(DecodeError::LibraryUnavailable(reason)) if options.mode == DecodeMode::Preview => {
+            let image = fallback_preview(source, reason)?;
+            return Ok(DecodedImage { image, metadata: ImageMetadata::default() });
+        }
+        result => result?,
+    };
+    raw.set_progress(progress.clone());
+    raw.open(source)?;
+    let metadata = raw.metadata();
+    let image = match options.mode {
+        DecodeMode::Preview => decode_preview_or_render(&mut raw, source, progress)?,
+        DecodeMode::HalfSize | DecodeMode::Full => render(
+            &mut raw,
+            options.mode == DecodeMode::HalfSize,
+            options.depth,
+            &options.params,
+        )?,
+    };
+    let image = if options.auto_orient { apply_flip(image, metadata.flip) } else { image };
+    Ok(DecodedImage { image, metadata })
+}
+
+/// Extracts the largest embedded JPEG without LibRaw.
+/// `reason` explains why LibRaw is unavailable and is reported if no preview is found.
+fn fallback_preview(source: &RawSource, reason: String) -> Result<DynamicImage, DecodeError> {
+    let data = source.bytes().map_err(|source| DecodeError::Io { stage: DecodeStage::Open, source })?;
+    extract_embedded_jpeg(&data).ok_or(DecodeError::LibraryUnavailable(format!(
+        "{}, and the file has no embedded JPEG preview",
+        reason
+    )))
+}
+
+/// The version of the LibRaw library in use, or why it cannot be used.
+fn libraw_version() -> Result<String, DecodeError> {
+    ffi::api()
+        .map(|api| api.version_string())
+        .map_err(DecodeError::LibraryUnavailable)
+}
+
+/// Reads only the metadata of a raw file; no pixel data is unpacked.
+fn read_metadata(source: &RawSource) -> Result<ImageMetadata, DecodeError> {
+    let mut raw = LibRaw::new()?;
+    raw.open(source)?;
+    Ok(raw.metadata())
+}
+
+/// Whether a thumbnail error means "there is no thumbnail we can use" rather than a broken file.
+fn is_missing_thumbnail(e: &DecodeError) -> bool {
+    matches!(
+        e.libraw_error(),
+        Some(
+            LibRawError::OutOfOrderCall
+                | LibRawError::NoThumbnail
+                | LibRawError::UnsupportedThumbnail
+                | LibRawError::NonexistentThumbnail
+        )
+    )
+}
+
+/// Whether `e` is a LibRaw error after which the handle must not be used any more.
+fn is_fatal(e: &DecodeError) -> bool {
+    e.libraw_error().is_some_and(|error| error.is_fatal())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn api() -> &'static LibRawApi {
+        ffi::api().unwrap()
+    }
+
+    /// An image header allocated the way LibRaw allocates them, so that
+    /// `libraw_dcraw_clear_mem` (a plain `free`) can release it.
+    fn allocated_image() -> *mut LibRawProcessedImage {
+        let image = unsafe { libc::calloc(1, std::mem::size_of::<LibRawProcessedImage>()) };
+        assert!(!image.is_null());
+        image as *mut LibRawProcessedImage
+    }
+
+    #[test]
+    fn failed_open_closes_the_handle_once() {
+        // Each handle is closed exactly once on drop; a double close aborts the test binary.
+        for _ in 0..100 {
+            let mut raw = LibRaw::new().unwrap();
+            assert!(raw.open(&RawSource::from_path("/nonexistent/image.arw")).is_err());
+            assert!(raw.unpack().is_err());
+        }
+    }
+
+    #[test]
+    fn images_are_not_made_without_an_open_file() {
+        let raw = LibRaw::new().unwrap();
+        assert!(raw.make_mem_thumb().is_err());
+        assert!(raw.make_mem_image().is_err());
+    }
+
+    #[test]
+    fn processed_image_is_cleared_once_on_drop() {
+        let image = ProcessedImage::from_raw(api(), allocated_image(), 0).unwrap();
+        assert!(image.data().is_empty());
+        // Dropping clears the memory; clearing it a second time would be caught by the allocator.
+        drop(image);
+    }
+
+    #[test]
+    fn image_returned_with_an_error_is_cleared() {
+        // LibRaw may hand back memory together with an error; `from_raw` owns it either way.
+        assert_eq!(ProcessedImage::from_raw(api(), allocated_image(), -4).err(), Some(-4));
+        assert_eq!(ProcessedImage::from_raw(api(), std::ptr::null_mut(), -4).err(), Some(-4));
+    }
+}
@@ -0,0 +1,119 @@
+/*
+   Field accessors for libraw_data_t.
+
+   The LibRaw C API only has setters for a handful of output parameters and
+   libraw_data_t is far too large to mirror in Rust, so these small functions
+   read and write the fields the viewer needs. They never call into LibRaw
+   itself, only touch the struct layout from the headers they are built with.
+ */
+
+#include <string.h>
+#include <libraw/libraw.h>
+
+void lrv_set_half_size(libraw_data_t *data, int half_size)
+{
+  data->params.half_size = half_size;
+}
+
+/* Shooting metadata, flattened into a struct that is mirrored in decoder.rs. */
+typedef struct
+{
+  char make[64];
+  char model[64];
+  char lens[128];
+  char serial[64];
+  char artist[64];
+  float iso_speed;
+  float shutter;
+  float aperture;
+  float focal_len;
+  long long timestamp;
+  float latitude[3];
+  float longitude[3];
+  float altitude;
+  char latref;
+  char longref;
+  char altref;
+  char gpsparsed;
+  float flash_used;
+  int flip;
+} lrv_metadata;
+
+static void lrv_copy_string(char *dst, const char *src, size_t size)
+{
+  strncpy(dst, src, size - 1);
+  dst[size - 1] = 0;
+}
+
+void lrv_get_metadata(const libraw_data_t *data, lrv_metadata *out)
+{
+  memset(out, 0, sizeof(*out));
+  lrv_copy_string(out->make, data->idata.make, sizeof(out->make));
+  lrv_copy_string(out->model, data->idata.model, sizeof(out->model));
+  /* Not every format fills lens.Lens; the maker notes often have it. */
+  lrv_copy_string(out->lens, data->lens.Lens[0] ? data->lens.Lens : data->lens.makernotes.Lens, sizeof(out->lens));
+  lrv_copy_string(out->serial, data->shootinginfo.BodySerial, sizeof(out->serial));
+  lrv_copy_string(out->artist, data->other.artist, sizeof(out->artist));
+  out->iso_speed = data->other.iso_speed;
+  out->shutter = data->other.shutter;
+  out->aperture = data->other.aperture;
+  out->focal_len = data->other.focal_len;
+  out->timestamp = (long long)data->other.timestamp;
+  memcpy(out->latitude, data->other.parsed_gps.latitude, sizeof(out->latitude));
+  memcpy(out->longitude, data->other.parsed_gps.longitude, sizeof(out->longitude));
+  out->altitude = data->other.parsed_gps.altitude;
+  out->latref = data->other.parsed_gps.latref;
+  out->longref = data->other.parsed_gps.longref;
+  out->altref = data->other.parsed_gps.altref;
+  out->gpsparsed = data->other.parsed_gps.gpsparsed;
+  out->flash_used = data->color.flash_used;
+  out->flip = data->sizes.flip;
+}
+
+/* Processing parameters, mirrored in decoder.rs. */
+typedef struct
+{
+  int use_camera_wb;
+  int use_auto_wb;
+  float user_mul[4];
+  int user_qual;
+  int exp_correc;
+  float exp_shift;
+  float exp_preser;
+  int highlight;
+  float threshold;
+  float bright;
+  int no_auto_bright;
+  double gamm[2];
+  int output_color;
+} lrv_params;
+
+void lrv_set_params(libraw_data_t *data, const lrv_params *in)
+{
+  libraw_output_params_t *params = &data->params;
+  params->use_camera_wb = in->use_camera_wb;
+  params->use_auto_wb = in->use_auto_wb;
+  memcpy(params->user_mul, in->user_mul, sizeof(params->user_mul));
+  params->user_qual = in->user_qual;
+  params->exp_correc = in->exp_correc;
+  params->exp_shift = in->exp_shift;
+  params->exp_preser = in->exp_preser;
+  params->highlight = in->highlight;
+  params->threshold = in->threshold;
+  params->bright = in->bright;
+  params->no_auto_bright = in->no_auto_bright;
+  params->gamm[0] = in->gamm[0];
+  params->gamm[1] = in->gamm[1];
+  params->output_color = in->output_color;
+}
+
+/* 0 keeps the sensor orientation, -1 lets LibRaw rotate by sizes.flip. */
+void lrv_set_user_flip(libraw_data_t *data, int flip)
+{
+  data->params.user_flip = flip;
+}
+
+int lrv_header_version(void)
+{
+  return LIBRAW_VERSION;
+}
@@ -1,89 +1,717 @@
+mod batch;
+mod batch_window;
+mod cache;
+mod cli;
 mod decoder;
+mod error;
+mod export;
+mod export_window;
+mod fallback;
+mod folder;
+#[cfg(feature = "libraw")]
+mod ffi;
+mod formats;
+mod image_cache;
+mod library;
+#[cfg(feature = "libraw")]
+mod libraw_backend;
+mod metadata;
+mod orientation;
+mod params;
+mod processing_panel;
+mod progress;
+#[cfg(feature = "rawloader")]
+mod rust_backend;
+mod source;
+mod thumbnails;
+mod tiles;
+mod viewport;
 
-use decoder::decode_arw_file;
+use batch_window::BatchWindow;
+use cache::{CachedDecoder, DiskCache};
+use cli::{Args, Command};
+use export_window::ExportWindow;
+use decoder::{BitDepth, DecodeMode, DecodeOptions, DecodedImage, RawDecoder};
+use error::DecodeError;
+use folder::{Folder, SortOrder};
+use formats::{has_raw_extension, sniff_file, RAW_EXTENSIONS};
+use image_cache::{CachedImage, ImageCache, ImageKey};
+use library::{LibraryView, MAX_THUMBNAIL_SIZE, MIN_THUMBNAIL_SIZE};
+use metadata::ImageMetadata;
+use orientation::rotate_quarter_turns;
+use progress::Progress;
+use source::RawSource;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+use thumbnails::ThumbnailCache;
+use tiles::TiledTexture;
+use viewport::{Viewport, Zoom};
 use eframe::egui;
 use rfd::FileDialog;
-use image::{ImageBuffer, Rgb};
+use image::DynamicImage;
+
+/// A decode running on a worker thread.
+struct DecodeJob {
+    key: ImageKey,
+    progress: Progress,
+    receiver: Receiver<Result<DecodedImage, DecodeError>>,
+}
+
+/// What the central panel shows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum View {
+    Single,
+    Library,
+}
 
 struct LibRawViewerApp {
-    texture: Option<egui::TextureHandle>,
-    image_data: Option<(Vec<u8>, u32, u32)>,
+    texture: Option<TiledTexture>,
+    image_data: Option<DynamicImage>,
+    metadata: Option<ImageMetadata>,
+    show_metadata: bool,
+    show_processing: bool,
+    /// What the shown image was decoded from, and how.
+    current: Option<ImageKey>,
+    options: DecodeOptions,
+    /// Manual clockwise quarter turns on top of the camera orientation; kept across re-renders.
+    quarter_turns: i32,
+    /// Zoom and pan; reset when another file is opened.
+    viewport: Viewport,
+    /// Every compiled-in backend, and the one in use.
+    backends: Vec<Arc<dyn RawDecoder>>,
+    decoder: Arc<dyn RawDecoder>,
+    /// The version of the active backend, or why it cannot be used.
+    backend_status: Result<String, String>,
+    /// The last error, shown in the status bar until the next successful action.
+    error: Option<String>,
+    /// Like `error`, for things that went as asked but are worth telling (a cancelled decode).
+    notice: Option<String>,
+    /// The decode in flight, if any. Starting another one cancels it.
+    job: Option<DecodeJob>,
+    /// The raw files around the current one, for previous/next navigation.
+    folder: Option<Folder>,
+    sort_order: SortOrder,
+    /// Decodes of the current file's neighbours still running, so stepping is instant.
+    prefetches: Vec<DecodeJob>,
+    /// Recently shown and prefetched images.
+    images: ImageCache,
+    view: View,
+    library: LibraryView,
+    thumbnails: ThumbnailCache,
+    /// Previews, thumbnails and metadata kept across sessions; `None` if it could not be opened.
+    disk_cache: Option<Arc<DiskCache>>,
+    /// Why the disk cache could not be opened, shown in its place in the status bar.
+    cache_error: Option<String>,
+    export: ExportWindow,
+    batch: BatchWindow,
+    /// A file or folder from the command line, opened in the first frame.
+    open_on_start: Option<PathBuf>,
 }
 
 impl LibRawViewerApp {
-    fn new() -> Self {
+    /// Creates the viewer with the given backends; the first one is used initially.
+    fn new(backends: Vec<Arc<dyn RawDecoder>>, disk_cache: Option<Arc<DiskCache>>) -> Self {
+        let decoder = backends.first().cloned().expect("at least one decoder backend");
         Self {
             texture: None,
             image_data: None,
+            metadata: None,
+            show_metadata: true,
+            show_processing: false,
+            current: None,
+            options: DecodeOptions::default(),
+            quarter_turns: 0,
+            viewport: Viewport::default(),
+            backend_status: decoder.version().map_err(|e| e.to_string()),
+            backends,
+            decoder,
+            error: None,
+            notice: None,
+            job: None,
+            folder: None,
+            sort_order: SortOrder::Name,
+            prefetches: Vec::new(),
+            images: ImageCache::new(ImageCache::max_bytes_from_env()),
+            view: View::Single,
+            library: LibraryView::default(),
+            thumbnails: ThumbnailCache::new(disk_cache.clone()),
+            disk_cache,
+            cache_error: None,
+            export: ExportWindow::default(),
+            batch: BatchWindow::default(),
+            open_on_start: None,
+        }
+    }
+
+    fn report_error(&mut self, message: String) {
+        self.notice = None;
+        self.error = Some(message);
+    }
+
+    /// Opens a file picked by the user and indexes its folder for navigation.
+    fn open_file(&mut self, path: &Path, ctx: &egui::Context) {
+        // Without a listing the file still opens, just without previous/next.
+        self.folder = Folder::open(path, self.sort_order, &self.decoder).ok();
+        self.discard_prefetches();
+        self.load_file(path, ctx);
+    }
+
+    /// Indexes a folder picked by the user and shows it as a grid.
+    fn open_folder(&mut self, dir: &Path) {
+        match Folder::open_dir(dir, self.sort_order, &self.decoder) {
+            Ok(folder) => {
+                self.folder = Some(folder);
+                self.discard_prefetches();
+                self.view = View::Library;
+                self.error = None;
+                self.notice = None;
+            }
+            Err(e) => self.report_error(format!("Error reading {}: {}", dir.display(), e)),
+        }
+    }
+
+    /// Shows the file `delta` positions away in the folder.
+    fn navigate(&mut self, delta: isize, ctx: &egui::Context) {
+        let Some(path) = self.folder.as_mut().and_then(|folder| folder.step(delta)).map(Path::to_path_buf) else {
+            return;
+        };
+        self.load_file(&path, ctx);
+    }
+
+    fn load_file(&mut self, path: &Path, ctx: &egui::Context) {
+        // Files picked through "All files" are sniffed so mislabeled raws still open
+        // and obviously unrelated files get a clear message instead of a LibRaw error code.
+        if !has_raw_extension(path) {
+            match sniff_file(path) {
+                Ok(Some(_)) => {}
+                Ok(None) => {
+                    self.report_error(format!("Error decoding {}: not a recognized raw file", path.display()));
+                    return;
+                }
+                Err(e) => {
+                    self.report_error(format!("Error reading {}: {}", path.display(), e));
+                    return;
+                }
+            }
+        }
+        if let Some(previous) = self.job.take() {
+            previous.progress.cancel();
         }
+        let key = ImageKey::new(path, self.decoder.name(), &self.options);
+        if let Some(cached) = self.images.take(&key) {
+            self.show_image(key, cached, ctx);
+            return;
+        }
+        // A neighbour that is still being prefetched becomes the main decode.
+        let prefetching = self.prefetches.iter().position(|job| job.key == key);
+        self.job = Some(match prefetching {
+            Some(index) => self.prefetches.swap_remove(index),
+            None => self.spawn_decode(key, ctx),
+        });
     }
 
-    fn load_arw(&mut self, path: &str, ctx: &egui::Context) {
-        match decode_arw_file(path) {
-            Ok((data, width, height)) => {
-                self.image_data = Some((data.clone(), width, height));
-                let pixels: Vec<egui::Color32> = data
-                    .chunks(3)
-                    .map(|chunk| egui::Color32::from_rgb(chunk[0], chunk[1], chunk[2]))
-                    .collect();
-                let color_image = egui::ColorImage {
-                    size: [width as usize, height as usize],
-                    pixels,
-                };
-                self.texture = Some(ctx.load_texture(
-                    "arw_thumb",
-                    color_image,
-                    egui::TextureOptions::default(),
-                ));
+    /// Decodes `key.path` with `key.options` on a worker thread.
+    fn spawn_decode(&self, key: ImageKey, ctx: &egui::Context) -> DecodeJob {
+        let (sender, receiver) = mpsc::channel();
+        let progress = Progress::new();
+        let decoder = self.decoder.clone();
+        let worker_options = key.options.clone();
+        let source = RawSource::from_path(&key.path);
+        let worker_progress = progress.clone();
+        let ctx = ctx.clone();
+        thread::spawn(move || {
+            let result = decoder.decode(&source, &worker_options, &worker_progress);
+            // The receiver is gone if the job was superseded; the result is simply dropped.
+            let _ = sender.send(result);
+            ctx.request_repaint();
+        });
+        DecodeJob { key, progress, receiver }
+    }
+
+    /// Starts decoding the files next to the current one that are not cached yet,
+    /// and cancels prefetches of anything else.
+    fn prefetch_neighbours(&mut self, ctx: &egui::Context) {
+        let wanted: Vec<ImageKey> = match &self.folder {
+            Some(folder) => folder
+                .neighbours()
+                .into_iter()
+                .map(|path| ImageKey::new(path, self.decoder.name(), &self.options))
+                .collect(),
+            None => Vec::new(),
+        };
+        self.prefetches.retain(|job| {
+            let keep = wanted.contains(&job.key);
+            if !keep {
+                job.progress.cancel();
+            }
+            keep
+        });
+        for key in wanted {
+            let running = self.prefetches.iter().any(|job| job.key == key);
+            if !running && !self.images.contains(&key) {
+                let job = self.spawn_decode(key, ctx);
+                self.prefetches.push(job);
+            }
+        }
+    }
+
+    /// Cancels and forgets all running prefetches, e.g. because they use another backend.
+    fn discard_prefetches(&mut self) {
+        for job in self.prefetches.drain(..) {
+            job.progress.cancel();
+        }
+    }
+
+    /// Uploads finished prefetches into the image cache. Failures are dropped;
+    /// they are reported if the file is opened.
+    fn poll_prefetches(&mut self, ctx: &egui::Context) {
+        let mut finished = Vec::new();
+        self.prefetches.retain(|job| match job.receiver.try_recv() {
+            Ok(result) => {
+                if let Ok(decoded) = result {
+                    finished.push((job.key.clone(), decoded));
+                }
+                false
+            }
+            Err(TryRecvError::Empty) => true,
+            Err(TryRecvError::Disconnected) => false,
+        });
+        for (key, decoded) in finished {
+            let texture = TiledTexture::new(ctx, "raw_image", &decoded.image);
+            let image = CachedImage {
+                image: decoded.image,
+                metadata: decoded.metadata,
+                quarter_turns: 0,
+                texture: Some(texture),
+            };
+            self.images.insert(key, image);
+        }
+    }
+
+    /// Takes the result of the running decode once it is ready.
+    fn poll_decode(&mut self, ctx: &egui::Context) {
+        let Some(job) = &self.job else {
+            return;
+        };
+        match job.receiver.try_recv() {
+            Ok(result) => {
+                let job = self.job.take().expect("job checked above");
+                self.finish_decode(job.key, result, ctx);
+            }
+            // Keep redrawing so the progress bar moves.
+            Err(TryRecvError::Empty) => ctx.request_repaint_after(Duration::from_millis(100)),
+            Err(TryRecvError::Disconnected) => {
+                let path = self.job.take().expect("job checked above").key.path;
+                self.report_error(format!("Error decoding {}: the decoder thread crashed", path.display()));
+            }
+        }
+    }
+
+    fn finish_decode(&mut self, key: ImageKey, result: Result<DecodedImage, DecodeError>, ctx: &egui::Context) {
+        match result {
+            Ok(decoded) => {
+                let image =
+                    CachedImage { image: decoded.image, metadata: decoded.metadata, quarter_turns: 0, texture: None };
+                self.show_image(key, image, ctx);
+            }
+            Err(e) if e.is_cancelled() => {
+                self.notice = Some(format!("Decoding {} was cancelled", key.path.display()));
             }
             Err(e) => {
-                eprintln!("Error decoding ARW: {}", e);
+                self.report_error(format!("Error decoding {}: {}", key.path.display(), e));
             }
         }
     }
 
-    fn save_png(&self, path: &str) -> Result<(), String> {
-        if let Some((data, width, height)) = &self.image_data {
-            let buffer: ImageBuffer<Rgb<u8>, _> =
-                ImageBuffer::from_raw(*width, *height, data.clone())
-                    .ok_or("Failed to create image buffer")?;
-            buffer.save(path).map_err(|e| e.to_string())
+    /// Displays `shown`, moving the image it replaces into the image cache.
+    fn show_image(&mut self, key: ImageKey, shown: CachedImage, ctx: &egui::Context) {
+        let same_file = self.current.as_ref().is_some_and(|current| current.path == key.path);
+        if let (Some(previous), Some(image), Some(metadata)) =
+            (self.current.take(), self.image_data.take(), self.metadata.take())
+        {
+            let texture = self.texture.take();
+            // Re-showing the same key replaces the image rather than caching a second copy of it.
+            if previous != key {
+                self.images.insert(previous, CachedImage { image, metadata, quarter_turns: self.quarter_turns, texture });
+            }
+        }
+        let CachedImage { mut image, metadata, quarter_turns, mut texture } = shown;
+        if same_file {
+            // A re-render keeps the rotation the user gave the file.
+            let turns = self.quarter_turns - quarter_turns;
+            if turns != 0 {
+                image = rotate_quarter_turns(image, turns);
+                texture = None;
+            }
         } else {
-            Err("No image loaded".into())
+            // Another file comes back the way it was left.
+            self.quarter_turns = quarter_turns;
+            self.viewport = Viewport::default();
+        }
+        self.current = Some(key);
+        self.error = None;
+        self.notice = None;
+        self.metadata = Some(metadata);
+        self.image_data = Some(image);
+        match texture {
+            Some(texture) => self.texture = Some(texture),
+            None => self.update_texture(ctx),
+        }
+        self.prefetch_neighbours(ctx);
+    }
+
+    /// Uploads `image_data` as the displayed texture.
+    fn update_texture(&mut self, ctx: &egui::Context) {
+        self.texture = self.image_data.as_ref().map(|image| TiledTexture::new(ctx, "raw_image", image));
+    }
+
+    /// Rotates the current image by clockwise quarter turns (negative for counter-clockwise).
+    fn rotate(&mut self, turns: i32, ctx: &egui::Context) {
+        if let Some(image) = self.image_data.take() {
+            self.quarter_turns = (self.quarter_turns + turns).rem_euclid(4);
+            self.image_data = Some(rotate_quarter_turns(image, turns));
+            self.update_texture(ctx);
+        }
+    }
+
+    /// Decodes the current file again, or the one still loading, with the current options.
+    fn reload(&mut self, ctx: &egui::Context) {
+        let loading = self.job.as_ref().map(|job| job.key.path.clone());
+        if let Some(path) = loading.or_else(|| self.current.as_ref().map(|current| current.path.clone())) {
+            self.load_file(&path, ctx);
         }
     }
 }
 
 impl eframe::App for LibRawViewerApp {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        egui::CentralPanel::default().show(ctx, |ui| {
-            if ui.button("Load ARW File").clicked() {
-                if let Some(path) = FileDialog::new().add_filter("ARW", &["arw"]).pick_file() {
-                    let path_str = path.to_string_lossy().to_string();
-                    self.load_arw(&path_str, ctx);
+        if let Some(path) = self.open_on_start.take() {
+            if path.is_dir() {
+                self.open_folder(&path);
+            } else {
+                self.open_file(&path, ctx);
+            }
+        }
+        self.poll_decode(ctx);
+        self.poll_prefetches(ctx);
+        if let Some(folder) = &mut self.folder {
+            if folder.poll() {
+                // The neighbours may have changed with the new order.
+                self.prefetch_neighbours(ctx);
+            } else if folder.is_sorting() {
+                ctx.request_repaint_after(Duration::from_millis(100));
+            }
+        }
+        if self.view == View::Library && self.thumbnails.poll(ctx) {
+            ctx.request_repaint_after(Duration::from_millis(100));
+        }
+        // The library view handles the arrow keys itself, to move the selection.
+        if self.view == View::Single && !ctx.wants_keyboard_input() {
+            let (previous, next) = ctx.input(|input| {
+                (input.key_pressed(egui::Key::ArrowLeft), input.key_pressed(egui::Key::ArrowRight))
+            });
+            if previous {
+                self.navigate(-1, ctx);
+            }
+            if next {
+                self.navigate(1, ctx);
+            }
+        }
+        egui::TopBottomPanel::bottom("status_bar").show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                match &self.backend_status {
+                    Ok(version) => {
+                        ui.label(format!("{} {}", self.decoder.name(), version));
+                    }
+                    Err(reason) => {
+                        ui.colored_label(
+                            ui.visuals().warn_fg_color,
+                            format!("{} Only embedded previews can be shown.", reason),
+                        );
+                    }
+                }
+                if let Some(error) = &self.error {
+                    ui.separator();
+                    ui.colored_label(ui.visuals().error_fg_color, error);
+                }
+                if let Some(notice) = &self.notice {
+                    ui.separator();
+                    ui.label(notice);
+                }
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    let megabytes = |bytes: u64| bytes as f64 / (1024.0 * 1024.0);
+                    if let Some(disk_cache) = self.disk_cache.clone() {
+                        if ui.button("Clear cache").clicked() {
+                            match disk_cache.clear() {
+                                Ok(()) => self.thumbnails.clear(),
+                                Err(e) => self.report_error(format!("Error clearing the cache: {}", e)),
+                            }
+                        }
+                        ui.label(format!(
+                            "Cache {:.0} / {:.0} MB",
+                            megabytes(disk_cache.size()),
+                            megabytes(disk_cache.max_bytes())
+                        ));
+                        ui.separator();
+                    } else if let Some(reason) = &self.cache_error {
+                        ui.label("Cache disabled").on_hover_text(reason);
+                        ui.separator();
+                    }
+                    ui.label(format!(
+                        "Memory {:.0} / {:.0} MB",
+                        megabytes(self.images.size() as u64),
+                        megabytes(self.images.max_bytes() as u64)
+                    ));
+                });
+            });
+        });
+        egui::SidePanel::right("metadata_panel").show_animated(ctx, self.show_metadata, |ui| {
+            ui.heading("Metadata");
+            match &self.metadata {
+                Some(metadata) => {
+                    egui::Grid::new("metadata_grid").num_columns(2).striped(true).show(ui, |ui| {
+                        for (label, value) in metadata.fields() {
+                            ui.label(label);
+                            ui.label(value);
+                            ui.end_row();
+                        }
+                    });
+                }
+                None => {
+                    ui.label("No image loaded");
                 }
             }
-            if let Some(texture) = &self.texture {
-                ui.image(texture, texture.size_vec2());
+        });
+        let mut rerender = false;
+        egui::SidePanel::left("processing_panel").show_animated(ctx, self.show_processing, |ui| {
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                rerender = processing_panel::show(ui, &mut self.options.params);
+            });
+        });
+        // Processing only affects raw renders, so there is nothing to redo for previews.
+        if rerender && self.options.mode != DecodeMode::Preview {
+            self.reload(ctx);
+        }
+        egui::CentralPanel::default().show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                if ui.button("Open Raw File").clicked() {
+                    if let Some(path) = FileDialog::new()
+                        .add_filter("Raw images", RAW_EXTENSIONS)
+                        .add_filter("All files", &["*"])
+                        .pick_file()
+                    {
+                        self.open_file(&path, ctx);
+                    }
+                }
+                if ui.button("Open Folder").clicked() {
+                    if let Some(dir) = FileDialog::new().pick_folder() {
+                        self.open_folder(&dir);
+                    }
+                }
+                if ui.selectable_label(self.export.open, "Export").clicked() {
+                    self.export.open = !self.export.open;
+                }
+                if ui.selectable_label(self.batch.open, "Batch convert").clicked() {
+                    self.batch.open = !self.batch.open;
+                }
+                if let Some((position, total)) = self.folder.as_ref().map(Folder::position) {
+                    ui.separator();
+                    if ui.selectable_label(self.view == View::Library, "Library").clicked() {
+                        self.view = if self.view == View::Library { View::Single } else { View::Library };
+                    }
+                    if ui.add_enabled(position > 1, egui::Button::new("Previous")).clicked() {
+                        self.navigate(-1, ctx);
+                    }
+                    ui.label(format!("{} / {}", position, total));
+                    if ui.add_enabled(position < total, egui::Button::new("Next")).clicked() {
+                        self.navigate(1, ctx);
+                    }
+                    let previous_order = self.sort_order;
+                    egui::ComboBox::from_id_source("sort_order")
+                        .selected_text(format!("Sort: {}", self.sort_order.label()))
+                        .show_ui(ui, |ui| {
+                            for order in SortOrder::ALL {
+                                ui.selectable_value(&mut self.sort_order, order, order.label());
+                            }
+                        });
+                    if self.sort_order != previous_order {
+                        if let Some(folder) = &mut self.folder {
+                            folder.sort(self.sort_order, &self.decoder);
+                        }
+                    }
+                    if self.folder.as_ref().is_some_and(Folder::is_sorting) {
+                        ui.add(egui::Spinner::new());
+                    }
+                }
+                ui.separator();
+                if self.view == View::Library {
+                    ui.add(
+                        egui::Slider::new(&mut self.library.thumbnail_size, MIN_THUMBNAIL_SIZE..=MAX_THUMBNAIL_SIZE)
+                            .text("Size"),
+                    );
+                    return;
+                }
+                if ui.selectable_label(self.viewport.zoom() == Zoom::Fit, "Fit").clicked() {
+                    self.viewport.set_zoom(Zoom::Fit);
+                }
+                if ui.selectable_label(self.viewport.zoom() == Zoom::Fill, "Fill").clicked() {
+                    self.viewport.set_zoom(Zoom::Fill);
+                }
+                if ui.selectable_label(self.viewport.zoom() == Zoom::Scale(1.0), "100%").clicked() {
+                    self.viewport.set_zoom(Zoom::Scale(1.0));
+                }
+                if ui.button("-").clicked() {
+                    self.viewport.zoom_by(0.5);
+                }
+                if ui.button("+").clicked() {
+                    self.viewport.zoom_by(2.0);
+                }
+                if self.texture.is_some() {
+                    ui.label(format!("{:.0}%", self.viewport.percent()));
+                }
+            });
+            ui.horizontal(|ui| {
+                let previous = (self.options.mode, self.options.depth);
+                for mode in DecodeMode::ALL {
+                    // Raw renders need a working backend; previews have a pure-Rust fallback.
+                    let enabled = self.backend_status.is_ok() || mode == DecodeMode::Preview;
+                    let selected = self.options.mode == mode;
+                    if ui.add_enabled(enabled, egui::SelectableLabel::new(selected, mode.label())).clicked() {
+                        self.options.mode = mode;
+                    }
+                }
+                ui.separator();
+                let mut sixteen_bit = self.options.depth == BitDepth::Sixteen;
+                ui.add_enabled(
+                    self.options.mode != DecodeMode::Preview,
+                    egui::Checkbox::new(&mut sixteen_bit, "16-bit"),
+                );
+                self.options.depth = if sixteen_bit { BitDepth::Sixteen } else { BitDepth::Eight };
+                ui.separator();
+                if ui.button("Rotate left").clicked() {
+                    self.rotate(-1, ctx);
+                }
+                if ui.button("Rotate right").clicked() {
+                    self.rotate(1, ctx);
+                }
+                ui.separator();
+                ui.toggle_value(&mut self.show_processing, "Processing");
+                ui.toggle_value(&mut self.show_metadata, "Metadata");
+                if self.backends.len() > 1 {
+                    ui.separator();
+                    let previous_backend = self.decoder.name();
+                    egui::ComboBox::from_id_source("backend")
+                        .selected_text(self.decoder.name())
+                        .show_ui(ui, |ui| {
+                            for backend in &self.backends {
+                                if ui.selectable_label(backend.name() == self.decoder.name(), backend.name()).clicked() {
+                                    self.decoder = backend.clone();
+                                }
+                            }
+                        });
+                    if self.decoder.name() != previous_backend {
+                        self.backend_status = self.decoder.version().map_err(|e| e.to_string());
+                        self.discard_prefetches();
+                        self.thumbnails.clear();
+                        self.reload(ctx);
+                    }
+                }
+                if (self.options.mode, self.options.depth) != previous {
+                    self.reload(ctx);
+                }
+            });
+            if let Some(job) = &self.job {
+                ui.horizontal(|ui| {
+                    ui.add(egui::Spinner::new());
+                    let stage = job.progress.stage();
+                    let text = if stage.is_empty() { "Decoding…".to_string() } else { stage };
+                    ui.add(egui::ProgressBar::new(job.progress.fraction()).text(text).desired_width(240.0));
+                    if ui.button("Cancel").clicked() {
+                        job.progress.cancel();
+                    }
+                });
             }
-            if ui.button("Save as PNG").clicked() {
-                if let Some(save_path) = FileDialog::new().save_file() {
-                    let save_path_str = save_path.to_string_lossy().to_string();
-                    match self.save_png(&save_path_str) {
-                        Ok(_) => println!("Saved PNG to {}", save_path_str),
-                        Err(e) => eprintln!("Error saving PNG: {}", e),
+            match self.view {
+                View::Library => {
+                    let open = match &mut self.folder {
+                        Some(folder) => self.library.show(ui, folder, &mut self.thumbnails, &self.decoder),
+                        None => None,
+                    };
+                    if let Some(path) = open {
+                        self.view = View::Single;
+                        self.load_file(&path, ctx);
+                    }
+                }
+                View::Single => {
+                    if let Some(texture) = &self.texture {
+                        self.viewport.show(ui, texture);
                     }
                 }
             }
         });
+        let source = self.current.as_ref().map(|current| current.path.as_path());
+        self.export.show(ctx, self.image_data.as_ref(), source);
+        self.batch.show(ctx, &self.decoder, &self.options.params, self.folder.as_ref());
     }
 }
 
-fn main() {
+fn main() -> ExitCode {
+    let args = match Args::parse(std::env::args_os().skip(1)) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, cli::USAGE);
+            return ExitCode::from(2);
+        }
+    };
+    if args.command != Command::Gui {
+        return cli::run(&args);
+    }
     let native_options = eframe::NativeOptions::default();
-    eframe::run_native(
-        "LibRaw ARW Viewer",
+    let result = eframe::run_native(
+        "LibRaw Viewer",
         native_options,
-        Box::new(|_cc| Box::new(LibRawViewerApp::new())),
+        Box::new(move |_cc| {
+            let (disk_cache, cache_error) = match open_disk_cache() {
+                Ok(disk_cache) => (disk_cache, None),
+                Err(e) => (None, Some(e)),
+            };
+            let mut app = LibRawViewerApp::new(cached_backends(disk_cache.as_ref()), disk_cache);
+            app.cache_error = cache_error;
+            app.open_on_start = args.inputs.into_iter().next();
+            Box::new(app)
+        }),
     );
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Could not start the viewer: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// The disk cache, `None` if it is disabled, or why it could not be opened.
+fn open_disk_cache() -> Result<Option<Arc<DiskCache>>, String> {
+    let max_bytes = DiskCache::max_bytes_from_env();
+    match DiskCache::default_dir() {
+        Some(dir) if max_bytes > 0 => DiskCache::open(dir.clone(), max_bytes)
+            .map(|cache| Some(Arc::new(cache)))
+            .map_err(|e| format!("Could not open {}: {}", dir.display(), e)),
+        _ => Ok(None),
+    }
+}
+
+/// Every backend, wrapped to use `disk_cache` if there is one.
+fn cached_backends(disk_cache: Option<&Arc<DiskCache>>) -> Vec<Arc<dyn RawDecoder>> {
+    decoder::backends()
+        .into_iter()
+        .map(|backend| match disk_cache {
+            Some(cache) => Arc::new(CachedDecoder::new(backend, cache.clone())) as Arc<dyn RawDecoder>,
+            None => backend,
+        })
+        .collect()
 }
@@ -0,0 +1,192 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// GPS position recorded by the camera.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GpsInfo {
+    /// Decimal degrees, negative south of the equator.
+    pub latitude: f64,
+    /// Decimal degrees, negative west of Greenwich.
+    pub longitude: f64,
+    /// Meters, negative below sea level.
+    pub altitude: f32,
+}
+
+/// Shooting metadata LibRaw parsed from a raw file.
+/// Numeric fields are zero and strings empty when the file does not record them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImageMetadata {
+    pub make: String,
+    pub model: String,
+    pub lens: String,
+    pub serial: String,
+    pub artist: String,
+    pub iso: f32,
+    /// Exposure time in seconds.
+    pub shutter: f32,
+    /// F-number.
+    pub aperture: f32,
+    /// Focal length in millimeters.
+    pub focal_length: f32,
+    /// Capture time in seconds since the Unix epoch (camera local time, as LibRaw reports it).
+    pub timestamp: Option<i64>,
+    pub gps: Option<GpsInfo>,
+    pub flash_fired: bool,
+    /// LibRaw's `sizes.flip` code, usually 0 (none), 3 (180°), 5 (90° CCW) or 6 (90° CW).
+    pub flip: i32,
+}
+
+impl ImageMetadata {
+    /// The EXIF orientation tag equivalent of `flip`.
+    pub fn exif_orientation(&self) -> u16 {
+        match self.flip & 7 {
+            1 => 2,
+            2 => 4,
+            3 => 3,
+            4 => 5,
+            5 => 8,
+            6 => 6,
+            7 => 7,
+            _ => 1,
+        }
+    }
+
+    pub fn capture_time(&self) -> Option<SystemTime> {
+        let timestamp = self.timestamp?;
+        if timestamp >= 0 {
+            UNIX_EPOCH.checked_add(Duration::from_secs(timestamp as u64))
+        } else {
+            UNIX_EPOCH.checked_sub(Duration::from_secs(timestamp.unsigned_abs()))
+        }
+    }
+
+    /// The camera name without a repeated manufacturer, e.g. "Sony ILCE-7RM4".
+    pub fn camera(&self) -> String {
+        if self.model.starts_with(&self.make) {
+            self.model.clone()
+        } else {
+            format!("{} {}", self.make, self.model).trim().to_string()
+        }
+    }
+
+    /// Human-readable label/value rows for every field that is set.
+    pub fn fields(&self) -> Vec<(&'static str, String)> {
+        let mut fields = Vec::new();
+        let camera = self.camera();
+        if !camera.is_empty() {
+            fields.push(("Camera", camera));
+        }
+        if !self.lens.is_empty() {
+            fields.push(("Lens", self.lens.clone()));
+        }
+        if !self.serial.is_empty() {
+            fields.push(("Serial", self.serial.clone()));
+        }
+        if self.iso > 0.0 {
+            fields.push(("ISO", format!("{}", self.iso.round())));
+        }
+        if self.shutter > 0.0 {
+            fields.push(("Shutter", format_shutter(self.shutter)));
+        }
+        if self.aperture > 0.0 {
+            fields.push(("Aperture", format!("f/{:.1}", self.aperture)));
+        }
+        if self.focal_length > 0.0 {
+            fields.push(("Focal length", format!("{:.0} mm", self.focal_length)));
+        }
+        if let Some(timestamp) = self.timestamp {
+            fields.push(("Captured", format_timestamp(timestamp)));
+        }
+        if !self.artist.is_empty() {
+            fields.push(("Artist", self.artist.clone()));
+        }
+        if let Some(gps) = &self.gps {
+            fields.push((
+                "GPS",
+                format!("{:.6}, {:.6} ({:.0} m)", gps.latitude, gps.longitude, gps.altitude),
+            ));
+        }
+        fields.push(("Flash", if self.flash_fired { "Fired" } else { "Did not fire" }.to_string()));
+        fields.push(("Orientation", format!("{}", self.exif_orientation())));
+        fields
+    }
+}
+
+/// Formats an exposure time as a fraction below one second, e.g. "1/250 s" or "2.5 s".
+pub fn format_shutter(seconds: f32) -> String {
+    if seconds < 1.0 {
+        format!("1/{:.0} s", 1.0 / seconds)
+    } else {
+        format!("{:.1} s", seconds)
+    }
+}
+
+/// Formats seconds since the Unix epoch as "YYYY-MM-DD HH:MM:SS".
+pub fn format_timestamp(timestamp: i64) -> String {
+    let days = timestamp.div_euclid(86_400);
+    let seconds = timestamp.rem_euclid(86_400);
+    // Civil-from-days (Howard Hinnant's algorithm), avoids pulling in a date crate.
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        seconds / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timestamps_are_calendar_dates() {
+        assert_eq!(format_timestamp(0), "1970-01-01 00:00:00");
+        assert_eq!(format_timestamp(951_782_400), "2000-02-29 00:00:00");
+        assert_eq!(format_timestamp(1_700_000_000), "2023-11-14 22:13:20");
+        assert_eq!(format_timestamp(-1), "1969-12-31 23:59:59");
+    }
+
+    #[test]
+    fn short_exposures_are_fractions() {
+        assert_eq!(format_shutter(1.0 / 250.0), "1/250 s");
+        assert_eq!(format_shutter(0.5), "1/2 s");
+        assert_eq!(format_shutter(1.0), "1.0 s");
+        assert_eq!(format_shutter(2.5), "2.5 s");
+    }
+
+    #[test]
+    fn fields_skip_what_the_file_does_not_record() {
+        let metadata = ImageMetadata {
+            make: "Sony".into(),
+            model: "ILCE-7RM4".into(),
+            shutter: 1.0 / 250.0,
+            aperture: 2.8,
+            ..Default::default()
+        };
+        let fields: Vec<(&str, String)> = vec![
+            ("Camera", "Sony ILCE-7RM4".into()),
+            ("Shutter", "1/250 s".into()),
+            ("Aperture", "f/2.8".into()),
+            ("Flash", "Did not fire".into()),
+            ("Orientation", "1".into()),
+        ];
+        assert_eq!(metadata.fields(), fields);
+    }
+
+    #[test]
+    fn camera_does_not_repeat_the_make() {
+        let metadata = ImageMetadata { make: "Canon".into(), model: "Canon EOS R5".into(), ..Default::default() };
+        assert_eq!(metadata.camera(), "Canon EOS R5");
+    }
+}
@@ -0,0 +1,129 @@
+use image::DynamicImage;
+
+/// Applies a LibRaw `sizes.flip` code to an image stored in sensor orientation.
+/// Bit 4 transposes, bit 2 flips vertically and bit 1 flips horizontally, as in dcraw's `flip_index`.
+pub fn apply_flip(image: DynamicImage, flip: i32) -> DynamicImage {
+    match flip & 7 {
+        1 => image.fliph(),
+        2 => image.flipv(),
+        3 => image.rotate180(),
+        // Transpose.
+        4 => image.rotate90().fliph(),
+        5 => image.rotate270(),
+        6 => image.rotate90(),
+        // Transverse.
+        7 => image.rotate90().flipv(),
+        _ => image,
+    }
+}
+
+/// Converts an EXIF orientation tag (1-8) into the equivalent LibRaw flip code.
+#[cfg_attr(not(feature = "rawloader"), allow(dead_code))]
+pub fn flip_from_exif(orientation: u16) -> i32 {
+    match orientation {
+        2 => 1,
+        3 => 3,
+        4 => 2,
+        5 => 4,
+        6 => 6,
+        7 => 7,
+        8 => 5,
+        _ => 0,
+    }
+}
+
+/// Rotates by a number of clockwise quarter turns (negative turns rotate counter-clockwise).
+pub fn rotate_quarter_turns(image: DynamicImage, turns: i32) -> DynamicImage {
+    match turns.rem_euclid(4) {
+        1 => image.rotate90(),
+        2 => image.rotate180(),
+        3 => image.rotate270(),
+        _ => image,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::GrayImage;
+
+    /// A 3x2 image whose pixels number themselves row by row:
+    /// ```text
+    /// 0 1 2
+    /// 3 4 5
+    /// ```
+    fn numbered() -> DynamicImage {
+        DynamicImage::ImageLuma8(GrayImage::from_fn(3, 2, |x, y| image::Luma([(y * 3 + x) as u8])))
+    }
+
+    fn rows(image: &DynamicImage) -> Vec<Vec<u8>> {
+        let gray = image.to_luma8();
+        (0..gray.height()).map(|y| (0..gray.width()).map(|x| gray.get_pixel(x, y)[0]).collect()).collect()
+    }
+
+    #[test]
+    fn apply_flip_matches_dcraw_codes() {
+        let cases: [(i32, Vec<Vec<u8>>); 8] = [
+            (0, vec![vec![0, 1, 2], vec![3, 4, 5]]),
+            (1, vec![vec![2, 1, 0], vec![5, 4, 3]]),
+            (2, vec![vec![3, 4, 5], vec![0, 1, 2]]),
+            (3, vec![vec![5, 4, 3], vec![2, 1, 0]]),
+            // Transpose: across the main diagonal.
+            (4, vec![vec![0, 3], vec![1, 4], vec![2, 5]]),
+            // 90° counter-clockwise.
+            (5, vec![vec![2, 5], vec![1, 4], vec![0, 3]]),
+            // 90° clockwise.
+            (6, vec![vec![3, 0], vec![4, 1], vec![5, 2]]),
+            // Transverse: across the anti-diagonal.
+            (7, vec![vec![5, 2], vec![4, 1], vec![3, 0]]),
+        ];
+        for (flip, expected) in cases {
+            assert_eq!(rows(&apply_flip(numbered(), flip)), expected, "flip {}", flip);
+        }
+    }
+
+    #[test]
+    fn apply_flip_ignores_bits_above_the_low_three() {
+        assert_eq!(rows(&apply_flip(numbered(), 8 | 6)), rows(&apply_flip(numbered(), 6)));
+    }
+
+    #[test]
+    fn exif_orientations_map_to_flip_codes() {
+        let cases = [
+            // Normal.
+            (1, 0),
+            // Mirrored horizontally.
+            (2, 1),
+            // Rotated 180°.
+            (3, 3),
+            // Mirrored vertically.
+            (4, 2),
+            // Mirrored horizontally, then rotated 270° clockwise: the transpose.
+            (5, 4),
+            // Rotated 90° clockwise.
+            (6, 6),
+            // Mirrored horizontally, then rotated 90° clockwise: the transverse.
+            (7, 7),
+            // Rotated 270° clockwise.
+            (8, 5),
+        ];
+        for (orientation, flip) in cases {
+            assert_eq!(flip_from_exif(orientation), flip, "EXIF orientation {}", orientation);
+        }
+    }
+
+    #[test]
+    fn invalid_exif_orientations_leave_the_image_alone() {
+        assert_eq!(flip_from_exif(0), 0);
+        assert_eq!(flip_from_exif(9), 0);
+        assert_eq!(flip_from_exif(u16::MAX), 0);
+    }
+
+    #[test]
+    fn quarter_turns_wrap_in_both_directions() {
+        assert_eq!(rows(&rotate_quarter_turns(numbered(), 1)), rows(&apply_flip(numbered(), 6)));
+        assert_eq!(rows(&rotate_quarter_turns(numbered(), -1)), rows(&apply_flip(numbered(), 5)));
+        assert_eq!(rows(&rotate_quarter_turns(numbered(), 6)), rows(&apply_flip(numbered(), 3)));
+        assert_eq!(rows(&rotate_quarter_turns(numbered(), -4)), rows(&numbered()));
+    }
+}
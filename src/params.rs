@@ -0,0 +1,254 @@
+// The LibRaw codes and mappings are unused in builds with only the rawloader backend.
+#![cfg_attr(not(feature = "libraw"), allow(dead_code))]
+
+/// How white balance multipliers are chosen.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WhiteBalance {
+    /// LibRaw's daylight multipliers for the camera.
+    Daylight,
+    /// The multipliers the camera recorded for the shot.
+    Camera,
+    /// Multipliers computed by averaging the whole image.
+    Auto,
+    /// User multipliers for R, G, B, G2.
+    Custom([f32; 4]),
+}
+
+/// Demosaicing algorithm (`user_qual`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Demosaic {
+    Linear,
+    Vng,
+    Ppg,
+    Ahd,
+    Dcb,
+    Dht,
+    Aahd,
+}
+
+impl Demosaic {
+    pub const ALL: [Demosaic; 7] = [
+        Demosaic::Linear,
+        Demosaic::Vng,
+        Demosaic::Ppg,
+        Demosaic::Ahd,
+        Demosaic::Dcb,
+        Demosaic::Dht,
+        Demosaic::Aahd,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Demosaic::Linear => "Linear",
+            Demosaic::Vng => "VNG",
+            Demosaic::Ppg => "PPG",
+            Demosaic::Ahd => "AHD",
+            Demosaic::Dcb => "DCB",
+            Demosaic::Dht => "DHT",
+            Demosaic::Aahd => "AAHD",
+        }
+    }
+
+    pub(crate) fn user_qual(&self) -> i32 {
+        match self {
+            Demosaic::Linear => 0,
+            Demosaic::Vng => 1,
+            Demosaic::Ppg => 2,
+            Demosaic::Ahd => 3,
+            Demosaic::Dcb => 4,
+            Demosaic::Dht => 11,
+            Demosaic::Aahd => 12,
+        }
+    }
+}
+
+/// Highlight handling (`highlight`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HighlightMode {
+    Clip,
+    Unclip,
+    Blend,
+    /// Rebuild highlights; the level goes from 3 (favor whites) to 9 (favor colors).
+    Rebuild(u8),
+}
+
+impl HighlightMode {
+    pub(crate) fn code(&self) -> i32 {
+        match self {
+            HighlightMode::Clip => 0,
+            HighlightMode::Unclip => 1,
+            HighlightMode::Blend => 2,
+            HighlightMode::Rebuild(level) => (*level).clamp(3, 9) as i32,
+        }
+    }
+}
+
+/// Output transfer curve (`gamm[0]` is the inverse power, `gamm[1]` the toe slope).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GammaCurve {
+    Bt709,
+    Srgb,
+    Linear,
+    Custom { power: f64, slope: f64 },
+}
+
+impl GammaCurve {
+    pub(crate) fn gamm(&self) -> [f64; 2] {
+        match self {
+            GammaCurve::Bt709 => [1.0 / 2.222, 4.5],
+            GammaCurve::Srgb => [1.0 / 2.4, 12.92],
+            GammaCurve::Linear => [1.0, 1.0],
+            GammaCurve::Custom { power, slope } => [1.0 / power, *slope],
+        }
+    }
+}
+
+/// Output color space (`output_color`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorSpace {
+    Raw,
+    Srgb,
+    AdobeRgb,
+    WideGamut,
+    ProPhoto,
+    Xyz,
+    Aces,
+    DciP3,
+    Rec2020,
+}
+
+impl ColorSpace {
+    pub const ALL: [ColorSpace; 9] = [
+        ColorSpace::Raw,
+        ColorSpace::Srgb,
+        ColorSpace::AdobeRgb,
+        ColorSpace::WideGamut,
+        ColorSpace::ProPhoto,
+        ColorSpace::Xyz,
+        ColorSpace::Aces,
+        ColorSpace::DciP3,
+        ColorSpace::Rec2020,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ColorSpace::Raw => "Camera raw",
+            ColorSpace::Srgb => "sRGB",
+            ColorSpace::AdobeRgb => "Adobe RGB",
+            ColorSpace::WideGamut => "Wide Gamut",
+            ColorSpace::ProPhoto => "ProPhoto",
+            ColorSpace::Xyz => "XYZ",
+            ColorSpace::Aces => "ACES",
+            ColorSpace::DciP3 => "DCI-P3",
+            ColorSpace::Rec2020 => "Rec. 2020",
+        }
+    }
+
+    pub(crate) fn code(&self) -> i32 {
+        match self {
+            ColorSpace::Raw => 0,
+            ColorSpace::Srgb => 1,
+            ColorSpace::AdobeRgb => 2,
+            ColorSpace::WideGamut => 3,
+            ColorSpace::ProPhoto => 4,
+            ColorSpace::Xyz => 5,
+            ColorSpace::Aces => 6,
+            ColorSpace::DciP3 => 7,
+            ColorSpace::Rec2020 => 8,
+        }
+    }
+}
+
+/// Settings for `dcraw_process`, mapped onto `libraw_output_params_t`.
+/// The defaults match LibRaw's own defaults except that the camera white balance is used.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProcessingParams {
+    pub white_balance: WhiteBalance,
+    pub demosaic: Demosaic,
+    /// Exposure correction in stops, applied before demosaicing (-2 to +3).
+    pub exposure_ev: f32,
+    /// How much to protect highlights when brightening (0 to 1).
+    pub exposure_preserve: f32,
+    pub highlight: HighlightMode,
+    /// Wavelet denoising threshold; 0 disables it.
+    pub noise_threshold: f32,
+    pub brightness: f32,
+    pub auto_bright: bool,
+    pub gamma: GammaCurve,
+    pub color_space: ColorSpace,
+}
+
+impl Default for ProcessingParams {
+    fn default() -> Self {
+        Self {
+            white_balance: WhiteBalance::Camera,
+            demosaic: Demosaic::Ahd,
+            exposure_ev: 0.0,
+            exposure_preserve: 0.0,
+            highlight: HighlightMode::Clip,
+            noise_threshold: 0.0,
+            brightness: 1.0,
+            auto_bright: true,
+            gamma: GammaCurve::Bt709,
+            color_space: ColorSpace::Srgb,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn demosaic_maps_to_user_qual() {
+        let codes: Vec<i32> = Demosaic::ALL.iter().map(Demosaic::user_qual).collect();
+        assert_eq!(codes, [0, 1, 2, 3, 4, 11, 12]);
+    }
+
+    #[test]
+    fn highlight_modes_map_to_libraw_codes() {
+        assert_eq!(HighlightMode::Clip.code(), 0);
+        assert_eq!(HighlightMode::Unclip.code(), 1);
+        assert_eq!(HighlightMode::Blend.code(), 2);
+        assert_eq!(HighlightMode::Rebuild(5).code(), 5);
+    }
+
+    #[test]
+    fn rebuild_levels_are_clamped_to_the_libraw_range() {
+        // Levels below 3 would select clip, unclip or blend instead.
+        assert_eq!(HighlightMode::Rebuild(0).code(), 3);
+        assert_eq!(HighlightMode::Rebuild(2).code(), 3);
+        assert_eq!(HighlightMode::Rebuild(9).code(), 9);
+        assert_eq!(HighlightMode::Rebuild(200).code(), 9);
+    }
+
+    #[test]
+    fn gamma_curves_map_to_gamm() {
+        assert_eq!(GammaCurve::Bt709.gamm(), [1.0 / 2.222, 4.5]);
+        assert_eq!(GammaCurve::Srgb.gamm(), [1.0 / 2.4, 12.92]);
+        assert_eq!(GammaCurve::Linear.gamm(), [1.0, 1.0]);
+        // LibRaw takes the inverse of the power.
+        assert_eq!(GammaCurve::Custom { power: 2.0, slope: 3.0 }.gamm(), [0.5, 3.0]);
+    }
+
+    #[test]
+    fn color_spaces_map_to_output_color() {
+        // `output_color` numbers the spaces in the order `ALL` lists them.
+        for (index, color_space) in ColorSpace::ALL.iter().enumerate() {
+            assert_eq!(color_space.code(), index as i32, "{}", color_space.label());
+        }
+        assert_eq!(ColorSpace::Srgb.code(), 1);
+        assert_eq!(ColorSpace::Rec2020.code(), 8);
+    }
+
+    #[test]
+    fn defaults_follow_libraw_except_for_camera_white_balance() {
+        let params = ProcessingParams::default();
+        assert_eq!(params.white_balance, WhiteBalance::Camera);
+        assert_eq!(params.demosaic.user_qual(), 3);
+        assert_eq!(params.highlight.code(), 0);
+        assert_eq!(params.gamma.gamm(), [1.0 / 2.222, 4.5]);
+        assert_eq!(params.color_space.code(), 1);
+        assert!(params.auto_bright);
+    }
+}
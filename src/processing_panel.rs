@@ -0,0 +1,101 @@
+use crate::params::{ColorSpace, Demosaic, GammaCurve, HighlightMode, ProcessingParams, WhiteBalance};
+use eframe::egui;
+
+/// Draws the processing controls. Returns true once an edit is finished
+/// (a slider is released or a discrete value is picked) and the image should be re-rendered.
+pub fn show(ui: &mut egui::Ui, params: &mut ProcessingParams) -> bool {
+    let mut commit = false;
+
+    ui.heading("Processing");
+    ui.label("Applies to half-size and full raw renders.");
+
+    ui.separator();
+    ui.label("White balance");
+    ui.horizontal_wrapped(|ui| {
+        commit |= finished(ui.radio_value(&mut params.white_balance, WhiteBalance::Camera, "As shot"));
+        commit |= finished(ui.radio_value(&mut params.white_balance, WhiteBalance::Auto, "Auto"));
+        commit |= finished(ui.radio_value(&mut params.white_balance, WhiteBalance::Daylight, "Daylight"));
+        let custom = matches!(params.white_balance, WhiteBalance::Custom(_));
+        if ui.radio(custom, "Custom").clicked() && !custom {
+            params.white_balance = WhiteBalance::Custom([2.0, 1.0, 1.5, 1.0]);
+            commit = true;
+        }
+    });
+    if let WhiteBalance::Custom(mul) = &mut params.white_balance {
+        for (label, value) in ["R", "G", "B", "G2"].into_iter().zip(mul.iter_mut()) {
+            commit |= finished(ui.add(egui::Slider::new(value, 0.1..=8.0).text(label)));
+        }
+    }
+
+    ui.separator();
+    egui::ComboBox::from_label("Demosaic")
+        .selected_text(params.demosaic.label())
+        .show_ui(ui, |ui| {
+            for demosaic in Demosaic::ALL {
+                commit |= finished(ui.selectable_value(&mut params.demosaic, demosaic, demosaic.label()));
+            }
+        });
+
+    ui.separator();
+    commit |= finished(ui.add(egui::Slider::new(&mut params.exposure_ev, -2.0..=3.0).text("Exposure (EV)")));
+    commit |= finished(ui.add(egui::Slider::new(&mut params.exposure_preserve, 0.0..=1.0).text("Preserve highlights")));
+    commit |= finished(ui.add(egui::Slider::new(&mut params.brightness, 0.25..=4.0).text("Brightness")));
+    commit |= finished(ui.checkbox(&mut params.auto_bright, "Auto brightness"));
+
+    ui.separator();
+    ui.label("Highlights");
+    ui.horizontal_wrapped(|ui| {
+        commit |= finished(ui.radio_value(&mut params.highlight, HighlightMode::Clip, "Clip"));
+        commit |= finished(ui.radio_value(&mut params.highlight, HighlightMode::Unclip, "Unclip"));
+        commit |= finished(ui.radio_value(&mut params.highlight, HighlightMode::Blend, "Blend"));
+        let rebuild = matches!(params.highlight, HighlightMode::Rebuild(_));
+        if ui.radio(rebuild, "Rebuild").clicked() && !rebuild {
+            params.highlight = HighlightMode::Rebuild(5);
+            commit = true;
+        }
+    });
+    if let HighlightMode::Rebuild(level) = &mut params.highlight {
+        commit |= finished(ui.add(egui::Slider::new(level, 3..=9).text("Rebuild level")));
+    }
+
+    ui.separator();
+    commit |= finished(ui.add(egui::Slider::new(&mut params.noise_threshold, 0.0..=1000.0).text("Noise threshold")));
+
+    ui.separator();
+    ui.label("Gamma");
+    ui.horizontal_wrapped(|ui| {
+        commit |= finished(ui.radio_value(&mut params.gamma, GammaCurve::Bt709, "BT.709"));
+        commit |= finished(ui.radio_value(&mut params.gamma, GammaCurve::Srgb, "sRGB"));
+        commit |= finished(ui.radio_value(&mut params.gamma, GammaCurve::Linear, "Linear"));
+        let custom = matches!(params.gamma, GammaCurve::Custom { .. });
+        if ui.radio(custom, "Custom").clicked() && !custom {
+            params.gamma = GammaCurve::Custom { power: 2.2, slope: 4.5 };
+            commit = true;
+        }
+    });
+    if let GammaCurve::Custom { power, slope } = &mut params.gamma {
+        commit |= finished(ui.add(egui::Slider::new(power, 1.0..=3.0).text("Power")));
+        commit |= finished(ui.add(egui::Slider::new(slope, 1.0..=20.0).text("Toe slope")));
+    }
+
+    ui.separator();
+    egui::ComboBox::from_label("Color space")
+        .selected_text(params.color_space.label())
+        .show_ui(ui, |ui| {
+            for color_space in ColorSpace::ALL {
+                commit |= finished(ui.selectable_value(&mut params.color_space, color_space, color_space.label()));
+            }
+        });
+
+    ui.separator();
+    if ui.button("Reset").clicked() {
+        *params = ProcessingParams::default();
+        commit = true;
+    }
+    commit
+}
+
+/// Whether a widget edit is complete: discrete changes count immediately, drags once released.
+fn finished(response: egui::Response) -> bool {
+    (response.changed() && !response.dragged()) || response.drag_released()
+}
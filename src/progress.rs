@@ -0,0 +1,63 @@
+//! Progress reporting and cancellation for decodes running on worker threads.
+
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// A handle shared between the thread running a decode and the UI watching it.
+/// Clones refer to the same state.
+#[derive(Debug, Clone, Default)]
+pub struct Progress {
+    /// Shared with every `linked` progress.
+    cancelled: Arc<AtomicBool>,
+    state: Arc<ProgressState>,
+}
+
+#[derive(Debug, Default)]
+struct ProgressState {
+    /// The completed fraction as `f32` bits, so it can be updated without a lock.
+    fraction: AtomicU32,
+    /// A human-readable name of the current step.
+    stage: Mutex<String>,
+}
+
+impl Progress {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A progress for another decode that starts from zero but is cancelled together with
+    /// this one, e.g. for each file of a batch.
+    pub fn linked(&self) -> Self {
+        Self { cancelled: self.cancelled.clone(), state: Arc::default() }
+    }
+
+    /// Asks the decode to stop at the next opportunity.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Records the current step and the overall completed fraction (0 to 1).
+    /// The fraction never moves backwards, so steps that report out of order do not make the bar jump.
+    pub fn update(&self, stage: &str, fraction: f32) {
+        let fraction = fraction.clamp(0.0, 1.0);
+        let _ = self.state.fraction.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |current| {
+            (fraction > f32::from_bits(current)).then_some(fraction.to_bits())
+        });
+        let mut current = self.state.stage.lock().unwrap_or_else(|e| e.into_inner());
+        if *current != stage {
+            *current = stage.to_string();
+        }
+    }
+
+    pub fn fraction(&self) -> f32 {
+        f32::from_bits(self.state.fraction.load(Ordering::Relaxed))
+    }
+
+    pub fn stage(&self) -> String {
+        self.state.stage.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
@@ -0,0 +1,130 @@
+//! A pure-Rust decoder backend built on rawloader (parsing) and imagepipe (rendering).
+//!
+//! It supports fewer cameras than LibRaw and ignores `ProcessingParams`, but needs no C
+//! toolchain or system library, and is useful for comparing renders between backends.
+
+use crate::decoder::{BitDepth, DecodeMode, DecodeOptions, DecodedImage, RawDecoder};
+use crate::error::{DecodeError, DecodeStage};
+use crate::fallback::extract_embedded_jpeg;
+use crate::metadata::ImageMetadata;
+use crate::orientation::{apply_flip, flip_from_exif};
+use crate::progress::Progress;
+use crate::source::RawSource;
+use image::{imageops::FilterType, DynamicImage, ImageBuffer};
+use std::fs::File;
+use std::io::{BufReader, Cursor, Read};
+
+pub struct RustDecoder;
+
+impl RawDecoder for RustDecoder {
+    fn name(&self) -> &'static str {
+        "rawloader"
+    }
+
+    fn version(&self) -> Result<String, DecodeError> {
+        Ok("rawloader + imagepipe".to_string())
+    }
+
+    fn decode(
+        &self,
+        source: &RawSource,
+        options: &DecodeOptions,
+        progress: &Progress,
+    ) -> Result<DecodedImage, DecodeError> {
+        checkpoint(progress, "Reading metadata", 0.0)?;
+        let metadata = self.read_metadata(source)?;
+        let image = match options.mode {
+            DecodeMode::Preview => match embedded_preview(source)? {
+                Some(preview) if options.auto_orient => apply_flip(preview, metadata.flip),
+                Some(preview) => preview,
+                None => render(source, false, BitDepth::Eight, progress)?,
+            },
+            DecodeMode::HalfSize => render(source, true, options.depth, progress)?,
+            DecodeMode::Full => render(source, false, options.depth, progress)?,
+        };
+        Ok(DecodedImage { image, metadata })
+    }
+
+    fn decode_preview(&self, source: &RawSource) -> Result<DynamicImage, DecodeError> {
+        let flip = self.read_metadata(source)?.flip;
+        embedded_preview(source)?
+            .map(|preview| apply_flip(preview, flip))
+            .ok_or(DecodeError::UnsupportedFormat {
+                stage: DecodeStage::Thumbnail,
+                detail: "no embedded JPEG preview".to_string(),
+            })
+    }
+
+    fn read_metadata(&self, source: &RawSource) -> Result<ImageMetadata, DecodeError> {
+        // A dummy decode parses the headers without decoding the sensor data.
+        let raw = rawloader::decode_dummy(&mut *open(source)?).map_err(|e| DecodeError::UnsupportedFormat {
+            stage: DecodeStage::Open,
+            detail: format!("{:?}", e),
+        })?;
+        Ok(ImageMetadata {
+            make: raw.clean_make.clone(),
+            model: raw.clean_model.clone(),
+            flip: flip_from_exif(raw.orientation.to_u16()),
+            ..ImageMetadata::default()
+        })
+    }
+}
+
+/// Reports the next step, or stops if the decode was cancelled.
+/// rawloader and imagepipe cannot be interrupted, so cancellation takes effect between steps.
+fn checkpoint(progress: &Progress, stage: &str, fraction: f32) -> Result<(), DecodeError> {
+    if progress.is_cancelled() {
+        return Err(DecodeError::Cancelled);
+    }
+    progress.update(stage, fraction);
+    Ok(())
+}
+
+/// A reader over the raw data, whether it is on disk or in memory.
+fn open(source: &RawSource) -> Result<Box<dyn Read + '_>, DecodeError> {
+    Ok(match source {
+        RawSource::Path(path) => {
+            let file = File::open(path).map_err(|source| DecodeError::Io { stage: DecodeStage::Open, source })?;
+            Box::new(BufReader::new(file))
+        }
+        RawSource::Buffer(data) => Box::new(Cursor::new(&data[..])),
+    })
+}
+
+/// The largest embedded JPEG, found without parsing the container.
+fn embedded_preview(source: &RawSource) -> Result<Option<DynamicImage>, DecodeError> {
+    let data = source.bytes().map_err(|source| DecodeError::Io { stage: DecodeStage::Open, source })?;
+    Ok(extract_embedded_jpeg(&data))
+}
+
+/// Renders the sensor data with imagepipe's default pipeline (which also applies the orientation).
+fn render(source: &RawSource, half_size: bool, depth: BitDepth, progress: &Progress) -> Result<DynamicImage, DecodeError> {
+    checkpoint(progress, "Loading raw data", 0.1)?;
+    let raw = rawloader::decode(&mut *open(source)?).map_err(|e| DecodeError::UnsupportedFormat {
+        stage: DecodeStage::Unpack,
+        detail: format!("{:?}", e),
+    })?;
+    let process_error = |detail: String| DecodeError::UnsupportedFormat { stage: DecodeStage::Process, detail };
+    let mut pipeline =
+        imagepipe::Pipeline::new_from_source(imagepipe::ImageSource::Raw(raw)).map_err(process_error)?;
+    checkpoint(progress, "Processing", 0.4)?;
+    let image = match depth {
+        BitDepth::Eight => {
+            let output = pipeline.output_8bit(None).map_err(process_error)?;
+            ImageBuffer::from_raw(output.width as u32, output.height as u32, output.data)
+                .map(DynamicImage::ImageRgb8)
+        }
+        BitDepth::Sixteen => {
+            let output = pipeline.output_16bit(None).map_err(process_error)?;
+            ImageBuffer::from_raw(output.width as u32, output.height as u32, output.data)
+                .map(DynamicImage::ImageRgb16)
+        }
+    }
+    .ok_or_else(|| process_error("imagepipe returned a buffer of the wrong size".to_string()))?;
+    Ok(if half_size {
+        checkpoint(progress, "Resizing", 0.9)?;
+        image.resize_exact(image.width() / 2, image.height() / 2, FilterType::Triangle)
+    } else {
+        image
+    })
+}
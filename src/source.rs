@@ -0,0 +1,125 @@
+//! Where raw data comes from: a file on disk or bytes already in memory.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// The input of a decode. Buffers are reference-counted so a source can be cloned cheaply
+/// and handed to worker threads, and so LibRaw can keep reading from them while a handle is open.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum RawSource {
+    /// A file, opened by the backend itself. Paths do not need to be valid UTF-8.
+    Path(PathBuf),
+    /// The complete contents of a raw file, e.g. from an archive, stdin or an asset store.
+    Buffer(Arc<[u8]>),
+}
+
+impl RawSource {
+    pub fn from_path(path: impl AsRef<Path>) -> Self {
+        RawSource::Path(path.as_ref().to_path_buf())
+    }
+
+    pub fn from_bytes(data: impl Into<Arc<[u8]>>) -> Self {
+        RawSource::Buffer(data.into())
+    }
+
+    /// Reads the rest of a stream into memory. Raw containers reference data by absolute
+    /// offset, so the stream is buffered whole; this also works for pipes such as stdin.
+    pub fn from_reader<R: Read>(mut reader: R) -> io::Result<Self> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data)?;
+        Ok(RawSource::Buffer(data.into()))
+    }
+
+    /// The raw bytes, read from disk if necessary.
+    pub fn bytes(&self) -> io::Result<Cow<'_, [u8]>> {
+        match self {
+            RawSource::Path(path) => std::fs::read(path).map(Cow::Owned),
+            RawSource::Buffer(data) => Ok(Cow::Borrowed(data)),
+        }
+    }
+}
+
+impl From<PathBuf> for RawSource {
+    fn from(path: PathBuf) -> Self {
+        RawSource::Path(path)
+    }
+}
+
+impl From<&Path> for RawSource {
+    fn from(path: &Path) -> Self {
+        RawSource::from_path(path)
+    }
+}
+
+impl From<Vec<u8>> for RawSource {
+    fn from(data: Vec<u8>) -> Self {
+        RawSource::from_bytes(data)
+    }
+}
+
+impl fmt::Display for RawSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RawSource::Path(path) => write!(f, "{}", path.display()),
+            RawSource::Buffer(data) => write!(f, "<{} bytes in memory>", data.len()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A stream that cannot seek, like stdin or a pipe, handing out a few bytes per read.
+    struct Pipe<'a>(&'a [u8]);
+
+    impl Read for Pipe<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = buf.len().min(self.0.len()).min(3);
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn from_reader_buffers_a_whole_pipe() {
+        let data = b"II*\0\x08\0\0\0 and the rest of the file";
+        let source = RawSource::from_reader(Pipe(data)).unwrap();
+        assert_eq!(source, RawSource::from_bytes(data.to_vec()));
+        assert_eq!(source.bytes().unwrap().as_ref(), data);
+    }
+
+    #[test]
+    fn from_reader_reads_from_the_current_position() {
+        let mut reader = io::Cursor::new(b"headerbody".to_vec());
+        reader.set_position(6);
+        assert_eq!(RawSource::from_reader(reader).unwrap(), RawSource::from_bytes(b"body".to_vec()));
+    }
+
+    #[test]
+    fn from_reader_passes_errors_on() {
+        struct Broken;
+        impl Read for Broken {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::new(io::ErrorKind::BrokenPipe, "closed"))
+            }
+        }
+        assert_eq!(RawSource::from_reader(Broken).unwrap_err().kind(), io::ErrorKind::BrokenPipe);
+    }
+
+    #[test]
+    fn paths_are_read_when_the_bytes_are_needed() {
+        let path = std::env::temp_dir().join(format!("libraw_viewer_source_{}.arw", std::process::id()));
+        std::fs::write(&path, b"raw bytes").unwrap();
+        let source = RawSource::from_path(&path);
+        assert_eq!(source.to_string(), path.display().to_string());
+        assert_eq!(source.bytes().unwrap().as_ref(), b"raw bytes");
+        std::fs::remove_file(&path).unwrap();
+        assert!(source.bytes().is_err());
+        assert_eq!(RawSource::from_bytes(vec![0; 4]).to_string(), "<4 bytes in memory>");
+    }
+}
@@ -0,0 +1,160 @@
+//! Embedded-preview thumbnails, generated on a pool of worker threads.
+
+use crate::cache::{backend_tag, DiskCache};
+use crate::decoder::RawDecoder;
+use crate::source::RawSource;
+use eframe::egui::{self, ColorImage, TextureHandle, TextureOptions};
+use image::RgbImage;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+
+/// Longest side of a generated thumbnail; the grid never shows them larger.
+pub const THUMBNAIL_SIDE: u32 = 256;
+
+/// A thumbnail as far as the UI is concerned.
+pub enum Thumbnail {
+    Pending,
+    Ready(TextureHandle),
+    Failed(String),
+}
+
+struct Request {
+    path: PathBuf,
+    decoder: Arc<dyn RawDecoder>,
+    generation: u64,
+}
+
+struct Response {
+    path: PathBuf,
+    generation: u64,
+    image: Result<ColorImage, String>,
+}
+
+/// Work shared with the pool threads.
+#[derive(Default)]
+struct Queue {
+    /// Taken from the back, so the cells that became visible most recently are done first.
+    requests: Vec<Request>,
+    shutdown: bool,
+}
+
+/// Thumbnails by path, and the pool that generates missing ones.
+pub struct ThumbnailCache {
+    thumbnails: HashMap<PathBuf, Thumbnail>,
+    queue: Arc<(Mutex<Queue>, Condvar)>,
+    responses: Receiver<Response>,
+    /// Bumped by `clear` so results requested before it are ignored.
+    generation: u64,
+}
+
+impl ThumbnailCache {
+    /// Starts one worker per CPU core. Thumbnails are read from and written to `disk` if given.
+    pub fn new(disk: Option<Arc<DiskCache>>) -> Self {
+        let queue = Arc::new((Mutex::new(Queue::default()), Condvar::new()));
+        let (sender, responses) = mpsc::channel();
+        let workers = thread::available_parallelism().map(|n| n.get()).unwrap_or(4);
+        for _ in 0..workers {
+            let queue = queue.clone();
+            let sender = sender.clone();
+            let disk = disk.clone();
+            thread::spawn(move || worker(&queue, &sender, disk.as_deref()));
+        }
+        Self { thumbnails: HashMap::new(), queue, responses, generation: 0 }
+    }
+
+    /// The thumbnail of `path`, queueing it with `decoder` if it has not been requested yet.
+    pub fn get(&mut self, path: &Path, decoder: &Arc<dyn RawDecoder>) -> &Thumbnail {
+        if !self.thumbnails.contains_key(path) {
+            let (queue, wakeup) = &*self.queue;
+            lock(queue).requests.push(Request {
+                path: path.to_path_buf(),
+                decoder: decoder.clone(),
+                generation: self.generation,
+            });
+            wakeup.notify_one();
+            self.thumbnails.insert(path.to_path_buf(), Thumbnail::Pending);
+        }
+        &self.thumbnails[path]
+    }
+
+    /// Uploads finished thumbnails. Returns true while any are still being generated.
+    pub fn poll(&mut self, ctx: &egui::Context) -> bool {
+        while let Ok(response) = self.responses.try_recv() {
+            if response.generation != self.generation {
+                continue;
+            }
+            let thumbnail = match response.image {
+                Ok(image) => {
+                    let name = format!("thumbnail_{}", response.path.display());
+                    Thumbnail::Ready(ctx.load_texture(name, image, TextureOptions::default()))
+                }
+                Err(e) => Thumbnail::Failed(e),
+            };
+            self.thumbnails.insert(response.path, thumbnail);
+        }
+        self.thumbnails.values().any(|thumbnail| matches!(thumbnail, Thumbnail::Pending))
+    }
+
+    /// Forgets every thumbnail and drops queued requests, e.g. after switching backends.
+    pub fn clear(&mut self) {
+        lock(&self.queue.0).requests.clear();
+        self.thumbnails.clear();
+        self.generation += 1;
+    }
+}
+
+impl Drop for ThumbnailCache {
+    fn drop(&mut self) {
+        let (queue, wakeup) = &*self.queue;
+        lock(queue).shutdown = true;
+        wakeup.notify_all();
+    }
+}
+
+fn lock(queue: &Mutex<Queue>) -> std::sync::MutexGuard<'_, Queue> {
+    queue.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn worker(queue: &(Mutex<Queue>, Condvar), sender: &Sender<Response>, disk: Option<&DiskCache>) {
+    let (queue, wakeup) = queue;
+    loop {
+        let request = {
+            let mut guard = lock(queue);
+            loop {
+                if guard.shutdown {
+                    return;
+                }
+                if let Some(request) = guard.requests.pop() {
+                    break request;
+                }
+                guard = wakeup.wait(guard).unwrap_or_else(|e| e.into_inner());
+            }
+        };
+        let image = make_thumbnail(&request, disk).map(|small| {
+            ColorImage::from_rgb([small.width() as usize, small.height() as usize], small.as_raw())
+        });
+        let response = Response { path: request.path, generation: request.generation, image };
+        if sender.send(response).is_err() {
+            return;
+        }
+    }
+}
+
+fn make_thumbnail(request: &Request, disk: Option<&DiskCache>) -> Result<RgbImage, String> {
+    let backend = backend_tag(request.decoder.as_ref());
+    if let Some(cached) = disk.and_then(|disk| disk.load_thumbnail(&request.path, &backend)) {
+        return Ok(cached);
+    }
+    let preview = request
+        .decoder
+        .decode_preview(&RawSource::from_path(&request.path))
+        .map_err(|e| e.to_string())?;
+    let small = preview.thumbnail(THUMBNAIL_SIDE, THUMBNAIL_SIDE).to_rgb8();
+    if let Some(disk) = disk {
+        disk.store_thumbnail(&request.path, &backend, &small);
+    }
+    Ok(small)
+}
@@ -0,0 +1,97 @@
+//! Displays images larger than the GPU texture limit as a mosaic of textures.
+//!
+//! Full-resolution renders from high-megapixel bodies (50-60 MP) exceed the maximum texture
+//! side of most GPUs, so the image is uploaded as tiles. A downsampled overview that fits in
+//! a single texture is drawn instead of the tiles whenever the image is shown smaller than it.
+
+use eframe::egui::{self, Color32, ColorImage, Pos2, Rect, TextureFilter, TextureHandle, TextureOptions, Vec2};
+use image::{imageops, DynamicImage, RgbImage};
+
+/// Upper bound for the side of a tile. Smaller tiles mean fewer wasted texels at the
+/// right and bottom edges and less to skip when only part of the image is visible.
+const MAX_TILE_SIDE: u32 = 2048;
+
+/// One texture of the mosaic and the part of the image it covers, in image pixels.
+struct Tile {
+    texture: TextureHandle,
+    pixels: Rect,
+}
+
+/// An image uploaded as one or more textures.
+pub struct TiledTexture {
+    width: u32,
+    height: u32,
+    tiles: Vec<Tile>,
+    /// The whole image scaled to fit a single tile; `None` if the image already fits in one.
+    overview: Option<TextureHandle>,
+}
+
+impl TiledTexture {
+    /// Converts `image` to 8-bit RGB and uploads it, split to respect the GPU's texture limit.
+    pub fn new(ctx: &egui::Context, name: &str, image: &DynamicImage) -> Self {
+        let max_side = ctx.input(|input| input.max_texture_side) as u32;
+        let tile_side = MAX_TILE_SIDE.min(max_side).max(1);
+        // The texture is always 8-bit; the decoded image keeps its full depth for export.
+        let rgb = image.to_rgb8();
+        let (width, height) = rgb.dimensions();
+        let mut tiles = Vec::new();
+        for y in (0..height).step_by(tile_side as usize) {
+            for x in (0..width).step_by(tile_side as usize) {
+                let w = tile_side.min(width - x);
+                let h = tile_side.min(height - y);
+                let tile = imageops::crop_imm(&rgb, x, y, w, h).to_image();
+                tiles.push(Tile {
+                    texture: upload(ctx, &format!("{}_{}_{}", name, x, y), &tile),
+                    pixels: Rect::from_min_size(Pos2::new(x as f32, y as f32), Vec2::new(w as f32, h as f32)),
+                });
+            }
+        }
+        let overview = (tiles.len() > 1).then(|| {
+            // `thumbnail` uses a fast box filter, which is good enough for a downscale this large.
+            let small = DynamicImage::ImageRgb8(rgb).thumbnail(tile_side, tile_side).into_rgb8();
+            upload(ctx, &format!("{}_overview", name), &small)
+        });
+        Self { width, height, tiles, overview }
+    }
+
+    /// The size of the full-resolution image in pixels.
+    pub fn size_vec2(&self) -> Vec2 {
+        Vec2::new(self.width as f32, self.height as f32)
+    }
+
+    /// GPU memory held by the textures, assuming four bytes per texel.
+    pub fn byte_size(&self) -> usize {
+        let texels = |size: [usize; 2]| size[0] * size[1];
+        let tiles: usize = self.tiles.iter().map(|tile| texels(tile.texture.size())).sum();
+        4 * (tiles + self.overview.as_ref().map_or(0, |overview| texels(overview.size())))
+    }
+
+    /// Draws the image stretched over `rect`, skipping tiles outside the painter's clip rect.
+    pub fn paint(&self, painter: &egui::Painter, rect: Rect) {
+        let uv = Rect::from_min_max(Pos2::ZERO, Pos2::new(1.0, 1.0));
+        if let Some(overview) = &self.overview {
+            let screen_width = rect.width() * painter.ctx().pixels_per_point();
+            if screen_width <= overview.size()[0] as f32 {
+                painter.image(overview.id(), rect, uv, Color32::WHITE);
+                return;
+            }
+        }
+        let scale = rect.size() / self.size_vec2();
+        for tile in &self.tiles {
+            let tile_rect = Rect::from_min_max(
+                rect.min + tile.pixels.min.to_vec2() * scale,
+                rect.min + tile.pixels.max.to_vec2() * scale,
+            );
+            if painter.clip_rect().intersects(tile_rect) {
+                painter.image(tile.texture.id(), tile_rect, uv, Color32::WHITE);
+            }
+        }
+    }
+}
+
+fn upload(ctx: &egui::Context, name: &str, image: &RgbImage) -> TextureHandle {
+    let size = [image.width() as usize, image.height() as usize];
+    // Magnified pixels stay sharp squares so focus can be judged; this also hides tile seams.
+    let options = TextureOptions { magnification: TextureFilter::Nearest, minification: TextureFilter::Linear };
+    ctx.load_texture(name, ColorImage::from_rgb(size, image.as_raw()), options)
+}
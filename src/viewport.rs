@@ -0,0 +1,142 @@
+//! Zooming and panning of the displayed image.
+
+use crate::tiles::TiledTexture;
+use eframe::egui::{self, Pos2, Rect, Sense, Vec2};
+
+/// Scroll distance (in points) that zooms by a factor of e.
+const SCROLL_ZOOM_SPEED: f32 = 200.0;
+/// Limits for free zoom, in screen pixels per image pixel.
+const MIN_SCALE: f32 = 0.01;
+const MAX_SCALE: f32 = 32.0;
+
+/// How the image is sized in the view.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Zoom {
+    /// The whole image is visible.
+    Fit,
+    /// The image covers the whole view.
+    Fill,
+    /// Screen pixels per image pixel, so 1.0 is 100% regardless of the display scale factor.
+    Scale(f32),
+}
+
+/// The zoom level and which part of the image is in the middle of the view.
+#[derive(Debug, Clone, Copy)]
+pub struct Viewport {
+    zoom: Zoom,
+    /// The image point at the center of the view, relative to the image size (0 to 1).
+    /// Kept relative so it stays on the same spot when the image is re-rendered at another size.
+    center: Vec2,
+    /// Screen pixels per image pixel in the last frame, for the zoom indicator.
+    scale: f32,
+}
+
+impl Default for Viewport {
+    fn default() -> Self {
+        Self { zoom: Zoom::Fit, center: Vec2::splat(0.5), scale: 1.0 }
+    }
+}
+
+impl Viewport {
+    pub fn zoom(&self) -> Zoom {
+        self.zoom
+    }
+
+    /// Switches to a zoom mode; fit and fill also recenter the image.
+    pub fn set_zoom(&mut self, zoom: Zoom) {
+        if matches!(zoom, Zoom::Fit | Zoom::Fill) {
+            self.center = Vec2::splat(0.5);
+        }
+        self.zoom = zoom;
+    }
+
+    /// Multiplies the current zoom, keeping the center of the view in place.
+    pub fn zoom_by(&mut self, factor: f32) {
+        self.zoom = Zoom::Scale((self.scale * factor).clamp(MIN_SCALE, MAX_SCALE));
+    }
+
+    /// The zoom level of the last frame in percent of the image resolution.
+    pub fn percent(&self) -> f32 {
+        self.scale * 100.0
+    }
+
+    /// Fills the available space with the image and handles wheel/pinch zoom,
+    /// drag panning and double-click toggling between fit and 100%.
+    pub fn show(&mut self, ui: &mut egui::Ui, texture: &TiledTexture) {
+        let (view, response) = ui.allocate_exact_size(ui.available_size(), Sense::click_and_drag());
+        let pixels_per_point = ui.ctx().pixels_per_point();
+        let image_size = texture.size_vec2();
+        // All geometry below is in points; `scale` is converted to screen pixels only for display.
+        let mut points_per_pixel = self.points_per_pixel(view.size(), image_size, pixels_per_point);
+
+        if let Some(cursor) = response.hover_pos() {
+            let (scroll, pinch) = ui.input(|input| (input.scroll_delta.y, input.zoom_delta()));
+            let factor = pinch * (scroll / SCROLL_ZOOM_SPEED).exp();
+            if factor != 1.0 {
+                let scale = (points_per_pixel * pixels_per_point * factor).clamp(MIN_SCALE, MAX_SCALE);
+                points_per_pixel = self.zoom_at(cursor, view, image_size, points_per_pixel, scale / pixels_per_point);
+                self.zoom = Zoom::Scale(scale);
+            }
+            if response.double_clicked() {
+                if self.zoom == Zoom::Fit {
+                    points_per_pixel = self.zoom_at(cursor, view, image_size, points_per_pixel, 1.0 / pixels_per_point);
+                    self.zoom = Zoom::Scale(1.0);
+                } else {
+                    self.set_zoom(Zoom::Fit);
+                    points_per_pixel = self.points_per_pixel(view.size(), image_size, pixels_per_point);
+                }
+            }
+        }
+        if response.dragged() && image_size.x > 0.0 && image_size.y > 0.0 {
+            self.center -= response.drag_delta() / (image_size * points_per_pixel);
+        }
+        if response.dragged() || response.hovered() {
+            ui.ctx().set_cursor_icon(if response.dragged() {
+                egui::CursorIcon::Grabbing
+            } else {
+                egui::CursorIcon::Grab
+            });
+        }
+        self.scale = points_per_pixel * pixels_per_point;
+
+        let displayed = image_size * points_per_pixel;
+        self.center = Vec2::new(
+            clamp_center(self.center.x, view.width(), displayed.x),
+            clamp_center(self.center.y, view.height(), displayed.y),
+        );
+        let image_rect = Rect::from_min_size(view.center() - self.center * displayed, displayed);
+        texture.paint(&ui.painter_at(view), image_rect);
+    }
+
+    /// Changes the scale so that the image point under `cursor` stays under it.
+    /// Returns the new scale in points per image pixel.
+    fn zoom_at(&mut self, cursor: Pos2, view: Rect, image_size: Vec2, old: f32, new: f32) -> f32 {
+        if image_size.x > 0.0 && image_size.y > 0.0 {
+            let offset = cursor - view.center();
+            let point = self.center + offset / (image_size * old);
+            self.center = point - offset / (image_size * new);
+        }
+        new
+    }
+
+    fn points_per_pixel(&self, view: Vec2, image: Vec2, pixels_per_point: f32) -> f32 {
+        if image.x <= 0.0 || image.y <= 0.0 {
+            return 1.0;
+        }
+        let ratio = view / image;
+        match self.zoom {
+            Zoom::Fit => ratio.x.min(ratio.y),
+            Zoom::Fill => ratio.x.max(ratio.y),
+            Zoom::Scale(scale) => scale / pixels_per_point,
+        }
+    }
+}
+
+/// Keeps the view inside the image along one axis, or centers the image if it is smaller than the view.
+fn clamp_center(center: f32, view: f32, displayed: f32) -> f32 {
+    if displayed <= view {
+        return 0.5;
+    }
+    let margin = view / 2.0 / displayed;
+    center.clamp(margin, 1.0 - margin)
+}